@@ -0,0 +1,74 @@
+// Scans large generated programs, one ascii and one mostly not, and prints the throughput.
+// Run with `cargo bench --bench scanner`.
+use rox::scanner::Scanner;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 10;
+
+fn generate(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source += &format!(
+            "# helper number {i}\n\
+             fun compute_{i}(first_value, second_value) {{\n\
+             \x20   var total_{i} = first_value * 1_000 + second_value / 2.5e-3;\n\
+             \x20   #( accumulate until the limit is reached )\n\
+             \x20   while (total_{i} < 0xffff and !done) {{\n\
+             \x20       total_{i} = total_{i} + {i}.75;\n\
+             \x20   }}\n\
+             \x20   print \"result of compute_{i}: \\t\" + total_{i};\n\
+             \x20   return total_{i} >= second_value;\n\
+             }}\n"
+        );
+    }
+    source
+}
+
+// the same kind of program, but with names, strings and long comments in Japanese and emoji
+fn generate_unicode(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source += &format!(
+            "# 補助関数その{i}、{comment}\n\
+             fun 計算_{i}(最初の値, 二番目の値) {{\n\
+             \x20   var 合計_{i} = 最初の値 * 1_000 + 二番目の値 / 2.5e-3;\n\
+             \x20   #( 上限に達するまで足し続ける 👨‍👩‍👧‍👦🇯🇵 )\n\
+             \x20   while (合計_{i} < 0xffff and !完了) {{\n\
+             \x20       合計_{i} = 合計_{i} + {i}.75;\n\
+             \x20   }}\n\
+             \x20   print \"計算_{i}の結果: \\t👍🏽 \" + 合計_{i};\n\
+             \x20   return 合計_{i} >= 二番目の値;\n\
+             }}\n",
+            comment = "とても長い説明文です。".repeat(20)
+        );
+    }
+    source
+}
+
+fn bench(name: &str, source: &str) {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = black_box(Scanner::new(black_box(source)).count());
+        best = best.min(start.elapsed());
+    }
+    let megabytes = source.len() as f64 / 1_000_000.0;
+    println!(
+        "{}: scanned {:.1} MB into {} tokens in {:.2?} (best of {}), {:.0} MB/s",
+        name,
+        megabytes,
+        tokens,
+        best,
+        RUNS,
+        megabytes / best.as_secs_f64()
+    );
+}
+
+fn main() {
+    bench("ascii", &generate(20_000));
+    bench("unicode", &generate_unicode(5_000));
+    // a single run of 40 thousand wide characters
+    bench("long run", &format!("# {}\n", "漢字".repeat(20_000)));
+}
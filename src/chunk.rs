@@ -1,28 +1,237 @@
+use crate::scanner::Location;
 use crate::value::Value;
 
-pub(crate) mod opcode {
-    pub const RETURN: u8 = 0;
-    pub const CONSTANT: u8 = 1;
-    pub const NEGATE: u8 = 2;
-    pub const ADD: u8 = 3;
-    pub const SUBTRACT: u8 = 4;
-    pub const MULTIPLY: u8 = 5;
-    pub const DIVIDE: u8 = 6;
-    pub const MODULO: u8 = 7;
-    pub const GREATER: u8 = 8;
-    pub const GREATER_EQUAL: u8 = 9;
-    pub const LESS: u8 = 10;
-    pub const LESS_EQUAL: u8 = 11;
-    pub const EQUAL: u8 = 12;
-    pub const NOT_EQUAL: u8 = 13;
+// Every instruction is an opcode byte followed by its operand, `operand_width` bytes that
+// are read as one big endian number. CLOSURE and CLOSURE_LONG are further followed by two
+// bytes for each upvalue of the function.
+macro_rules! opcodes {
+    ($($op:ident = $name:literal, $width:literal;)*) => {
+        #[repr(u8)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum OpCode {
+            $($op,)*
+        }
+
+        impl OpCode {
+            // in the order of their discriminants
+            const ALL: &[OpCode] = &[$(OpCode::$op,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(OpCode::$op => $name,)*
+                }
+            }
+
+            pub fn operand_width(self) -> usize {
+                match self {
+                    $(OpCode::$op => $width,)*
+                }
+            }
+        }
+    };
+}
+
+// name as shown by the disassembler, operand width
+opcodes! {
+    Return = "RETURN", 0;
+    Constant = "CONSTANT", 1;
+    Negate = "NEGATE", 0;
+    Add = "ADD", 0;
+    Subtract = "SUBTRACT", 0;
+    Multiply = "MULTIPLY", 0;
+    Divide = "DIVIDE", 0;
+    Modulo = "MODULO", 0;
+    Greater = "GREATER", 0;
+    GreaterEqual = "GREATER_EQUAL", 0;
+    Less = "LESS", 0;
+    LessEqual = "LESS_EQUAL", 0;
+    Equal = "EQUAL", 0;
+    NotEqual = "NOT_EQUAL", 0;
+    Nil = "NIL", 0;
+    True = "TRUE", 0;
+    False = "FALSE", 0;
+    Print = "PRINT", 0;
+    Pop = "POP", 0;
+    DefineGlobal = "DEFINE_GLOBAL", 1;
+    GetGlobal = "GET_GLOBAL", 1;
+    SetGlobal = "SET_GLOBAL", 1;
+    GetLocal = "GET_LOCAL", 1;
+    SetLocal = "SET_LOCAL", 1;
+    Not = "NOT", 0;
+    Jump = "JUMP", 2;
+    JumpIfFalse = "JUMP_IF_FALSE", 2;
+    Loop = "LOOP", 2;
+    Call = "CALL", 1;
+    Closure = "CLOSURE", 1;
+    GetUpvalue = "GET_UPVALUE", 1;
+    SetUpvalue = "SET_UPVALUE", 1;
+    CloseUpvalue = "CLOSE_UPVALUE", 0;
+    Class = "CLASS", 1;
+    GetProperty = "GET_PROPERTY", 1;
+    SetProperty = "SET_PROPERTY", 1;
+    Method = "METHOD", 1;
+    Inherit = "INHERIT", 0;
+    GetSuper = "GET_SUPER", 1;
+    Interpolate = "INTERPOLATE", 1;
+    List = "LIST", 1;
+    GetIndex = "GET_INDEX", 0;
+    SetIndex = "SET_INDEX", 0;
+    // duplicate the top one or two values, compound assignments read their target twice
+    Dup = "DUP", 0;
+    Dup2 = "DUP2", 0;
+    // CONSTANT and CLOSURE with a three byte index, for chunks with more than 256 constants
+    ConstantLong = "CONSTANT_LONG", 3;
+    ClosureLong = "CLOSURE_LONG", 3;
+    // the same for the instructions that take a name
+    DefineGlobalLong = "DEFINE_GLOBAL_LONG", 3;
+    GetGlobalLong = "GET_GLOBAL_LONG", 3;
+    SetGlobalLong = "SET_GLOBAL_LONG", 3;
+    ClassLong = "CLASS_LONG", 3;
+    GetPropertyLong = "GET_PROPERTY_LONG", 3;
+    SetPropertyLong = "SET_PROPERTY_LONG", 3;
+    MethodLong = "METHOD_LONG", 3;
+    GetSuperLong = "GET_SUPER_LONG", 3;
+}
+
+impl OpCode {
+    // the form with a three byte index of the instructions that index the constants or names
+    pub fn long_form(self) -> Option<OpCode> {
+        match self {
+            OpCode::Constant => Some(OpCode::ConstantLong),
+            OpCode::Closure => Some(OpCode::ClosureLong),
+            OpCode::DefineGlobal => Some(OpCode::DefineGlobalLong),
+            OpCode::GetGlobal => Some(OpCode::GetGlobalLong),
+            OpCode::SetGlobal => Some(OpCode::SetGlobalLong),
+            OpCode::Class => Some(OpCode::ClassLong),
+            OpCode::GetProperty => Some(OpCode::GetPropertyLong),
+            OpCode::SetProperty => Some(OpCode::SetPropertyLong),
+            OpCode::Method => Some(OpCode::MethodLong),
+            OpCode::GetSuper => Some(OpCode::GetSuperLong),
+            _ => None,
+        }
+    }
 }
 
-// When you add an opcode, don't forget to adjust the try_into implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid opcode {0}")]
+pub struct InvalidOpCode(pub u8);
+
+impl TryFrom<u8> for OpCode {
+    type Error = InvalidOpCode;
+
+    fn try_from(byte: u8) -> Result<OpCode, InvalidOpCode> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(InvalidOpCode(byte))
+    }
+}
 
 pub struct Chunk {
     code: Vec<u8>,
     constants: Vec<Value>,
-    lines: Vec<usize>,
+    // names of variables, properties and methods, kept apart from the constants so
+    // that a chunk full of literals still has room for them
+    names: Vec<String>,
+    locations: LocationTable,
+}
+
+// how many runs `location_at` decodes at most
+const CHECKPOINT_EVERY: usize = 32;
+
+// Source locations of the code as runs of bytes compiled from the same place. Every run is
+// stored as the change from the one before: the distance in code, the change in line and
+// in source index, and the column, each a LEB128 varint with zigzag for the signed ones.
+#[derive(Default)]
+struct LocationTable {
+    runs: Vec<u8>,
+    // every CHECKPOINT_EVERY-th run in full and where the run after it starts in `runs`
+    checkpoints: Vec<(Run, usize)>,
+    count: usize,
+    last: Option<Run>,
+}
+
+#[derive(Clone, Copy)]
+struct Run {
+    offset: usize,
+    location: Location,
+}
+
+impl LocationTable {
+    fn push(&mut self, offset: usize, location: Location) {
+        if let Some(last) = self.last {
+            if last.location.index == location.index {
+                return;
+            }
+            write_varint(&mut self.runs, (offset - last.offset) as u64);
+            let line = location.line as i64 - last.location.line as i64;
+            let index = location.index as i64 - last.location.index as i64;
+            write_varint(&mut self.runs, zigzag(line));
+            write_varint(&mut self.runs, zigzag(index));
+            write_varint(&mut self.runs, location.col as u64);
+        }
+        let run = Run { offset, location };
+        if self.count.is_multiple_of(CHECKPOINT_EVERY) {
+            self.checkpoints.push((run, self.runs.len()));
+        }
+        self.count += 1;
+        self.last = Some(run);
+    }
+
+    fn get(&self, offset: usize) -> Location {
+        let checkpoint = self.checkpoints.partition_point(|(run, _)| run.offset <= offset) - 1;
+        let (mut run, mut position) = self.checkpoints[checkpoint];
+        while position < self.runs.len() {
+            let next = run.offset + read_varint(&self.runs, &mut position) as usize;
+            if next > offset {
+                break;
+            }
+            let line = unzigzag(read_varint(&self.runs, &mut position));
+            let index = unzigzag(read_varint(&self.runs, &mut position));
+            let col = read_varint(&self.runs, &mut position);
+            run = Run {
+                offset: next,
+                location: Location {
+                    line: (run.location.line as i64 + line) as usize,
+                    col: col as usize,
+                    index: (run.location.index as i64 + index) as usize,
+                },
+            };
+        }
+        run.location
+    }
+
+    fn shrink(&mut self) {
+        self.runs.shrink_to_fit();
+        self.checkpoints.shrink_to_fit();
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
 }
 
 impl Chunk {
@@ -30,12 +239,17 @@ impl Chunk {
         Chunk {
             code: Vec::with_capacity(capacity),
             constants: Vec::with_capacity(const_capacity),
-            lines: Vec::with_capacity(capacity / 6),
+            names: Vec::new(),
+            locations: LocationTable::default(),
         }
     }
-    pub fn push_code(&mut self, code: u8, line: usize) {
+    pub fn push_code(&mut self, code: u8, location: Location) {
+        self.locations.push(self.code.len(), location);
         self.code.push(code);
-        self.lines.push(line);
+    }
+
+    pub fn patch_code(&mut self, offset: usize, code: u8) {
+        self.code[offset] = code;
     }
 
     pub fn push_constant(&mut self, constant: Value) -> usize {
@@ -43,16 +257,24 @@ impl Chunk {
         self.constants.len() -1
     }
 
-    pub fn shrink(&mut self, name: &str) {
-        let code_len = self.code.capacity();
-        let const_len = self.constants.capacity();
+    pub fn push_name(&mut self, name: String) -> usize {
+        self.names.push(name);
+        self.names.len() - 1
+    }
+
+    // frees the spare capacity once the chunk is complete
+    pub fn shrink(&mut self) {
         self.code.shrink_to_fit();
         self.constants.shrink_to_fit();
+        self.names.shrink_to_fit();
+        self.locations.shrink();
+    }
 
-        // DEBUG
-        println!("=== Chunk {} ===", name);
-        println!("Shrank byte code vector by {} to {} entries", code_len - self.code.capacity(), self.code.capacity());
-        println!("Shrank constant vector by {} to {} entries\n", const_len - self.constants.capacity(), self.constants.capacity());
+    // the operand of `width` bytes starting at `offset`
+    pub fn read_operand(&self, offset: usize, width: usize) -> usize {
+        self.code[offset..offset + width]
+            .iter()
+            .fold(0, |operand, &byte| operand << 8 | byte as usize)
     }
 
     pub fn code(&self) -> &[u8] {
@@ -63,7 +285,12 @@ impl Chunk {
         &self.constants
     }
 
-    pub fn lines(&self) -> &[usize] {
-        &self.lines
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // where the byte at `offset` was compiled from
+    pub fn location_at(&self, offset: usize) -> Location {
+        self.locations.get(offset)
     }
 }
@@ -0,0 +1,591 @@
+use crate::chunk::{Chunk, OpCode};
+use crate::compiler::FunctionKind;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expr::{CompoundOp, Expr, LocExpr};
+use crate::object::Function;
+use crate::scanner::Location;
+use crate::stmt::{ClassDecl, FunctionDecl, LocStmt, Stmt};
+use crate::value::Value;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Walks the tree produced by the parser and emits bytecode for the vm
+pub struct CodeGen {
+    diagnostics: Vec<Diagnostic>,
+    // one entry per function that is currently being compiled, the innermost one is last
+    states: Vec<FunctionState>,
+}
+
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    // index of every name in the chunk, so each one is only stored once
+    names: HashMap<String, usize>,
+}
+
+struct Local {
+    name: String,
+    // None while the initializer of the variable is being compiled
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+#[derive(PartialEq)]
+struct UpvalueRef {
+    // slot of the enclosing function's local, or index into the enclosing function's upvalues
+    index: u8,
+    is_local: bool,
+}
+
+impl FunctionState {
+    fn new(name: &str, arity: usize, kind: FunctionKind) -> Self {
+        // methods keep their receiver in slot zero so that `this` resolves to it
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        FunctionState {
+            function: Function {
+                name: name.to_string(),
+                arity,
+                chunk: Chunk::new(8, 4),
+                upvalue_count: 0,
+            },
+            kind,
+            // slot zero is taken by the function that is being called
+            locals: vec![Local {
+                name: slot_zero.to_string(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            names: HashMap::new(),
+        }
+    }
+}
+
+impl CodeGen {
+    pub fn generate(program: &[LocStmt]) -> Result<Function, Vec<Diagnostic>> {
+        let mut codegen = CodeGen {
+            diagnostics: Vec::new(),
+            states: vec![FunctionState::new("", 0, FunctionKind::Script)],
+        };
+        for (index, stmt) in program.iter().enumerate() {
+            match &stmt.stmt {
+                // the script hands the value of a trailing expression to whoever runs it
+                Stmt::Expression(expr) if index + 1 == program.len() => {
+                    codegen.expression(expr);
+                    codegen.emit_op(OpCode::Return, stmt.start);
+                }
+                _ => codegen.statement(stmt),
+            }
+        }
+        let last = program.last().map_or(Location::START, |stmt| stmt.end);
+        let (function, _) = codegen.end_function(last);
+        if codegen.diagnostics.is_empty() {
+            Ok(function)
+        } else {
+            Err(codegen.diagnostics)
+        }
+    }
+
+    fn report_error_at(&mut self, loc: &Location, message: &str) {
+        self.diagnostics
+            .push(Diagnostic::error(message, Span::point(*loc)));
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("No function is being compiled")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().function.chunk
+    }
+
+    fn emit(&mut self, code: u8, loc: Location) {
+        self.chunk().push_code(code, loc);
+    }
+
+    fn emit_op(&mut self, op: OpCode, loc: Location) {
+        self.emit(op as u8, loc);
+    }
+
+    // emits a jump with a placeholder operand and returns the operand's offset for patching
+    fn emit_jump(&mut self, code: OpCode, loc: Location) -> usize {
+        self.emit_op(code, loc);
+        self.emit(0xff, loc);
+        self.emit(0xff, loc);
+        self.chunk().code().len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, loc: &Location) {
+        // -2 to account for the jump's own operand
+        let jump = self.chunk().code().len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.report_error_at(loc, "Too much code to jump over");
+            return;
+        };
+        let [high, low] = jump.to_be_bytes();
+        self.chunk().patch_code(offset, high);
+        self.chunk().patch_code(offset + 1, low);
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, loc: &Location) {
+        self.emit_op(OpCode::Loop, *loc);
+        // +2 to also jump back over the loop's own operand
+        let jump = self.chunk().code().len() - loop_start + 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.report_error_at(loc, "Loop body too large");
+            return;
+        };
+        let [high, low] = jump.to_be_bytes();
+        self.emit(high, *loc);
+        self.emit(low, *loc);
+    }
+
+    // for instructions that take the name of a variable, property or method
+    fn emit_with_name(&mut self, code: OpCode, name: &str, loc: &Location) {
+        let index = match self.state().names.get(name) {
+            Some(&index) => index,
+            None => {
+                let index = self.chunk().push_name(name.to_string());
+                self.state().names.insert(name.to_string(), index);
+                index
+            }
+        };
+        if !self.emit_with_index(code, index, loc) {
+            self.report_error_at(loc, "Too many names in one chunk");
+        }
+    }
+
+    // loads a literal
+    fn emit_constant(&mut self, value: Value, loc: &Location) {
+        self.emit_with_constant(OpCode::Constant, value, loc);
+    }
+
+    fn emit_with_constant(&mut self, code: OpCode, value: Value, loc: &Location) {
+        let index = self.chunk().push_constant(value);
+        if !self.emit_with_index(code, index, loc) {
+            self.report_error_at(loc, "Too many constants in one chunk");
+        }
+    }
+
+    // past the first 256 entries the index takes three bytes and the long form is used,
+    // returns false if the index doesn't fit in that either
+    fn emit_with_index(&mut self, code: OpCode, index: usize, loc: &Location) -> bool {
+        let long_code = code.long_form().expect("Instruction has no long form");
+        if let Ok(index) = u8::try_from(index) {
+            self.emit_op(code, *loc);
+            self.emit(index, *loc);
+        } else if index < 1 << 24 {
+            let [_, high, middle, low] = (index as u32).to_be_bytes();
+            self.emit_op(long_code, *loc);
+            self.emit(high, *loc);
+            self.emit(middle, *loc);
+            self.emit(low, *loc);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    // initializers implicitly return the instance they were called on
+    fn emit_return(&mut self, loc: Location) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal, loc);
+            self.emit(0, loc);
+        } else {
+            self.emit_op(OpCode::Nil, loc);
+        }
+        self.emit_op(OpCode::Return, loc);
+    }
+
+    fn end_function(&mut self, loc: Location) -> (Function, Vec<UpvalueRef>) {
+        self.emit_return(loc);
+        let mut state = self.states.pop().expect("No function is being compiled");
+        state.function.chunk.shrink();
+        state.function.upvalue_count = state.upvalues.len();
+        (state.function, state.upvalues)
+    }
+
+    fn statement(&mut self, stmt: &LocStmt) {
+        let loc = stmt.start;
+        match &stmt.stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr);
+                self.emit_op(OpCode::Pop, loc);
+            }
+            Stmt::Print(expr) => {
+                self.expression(expr);
+                self.emit_op(OpCode::Print, loc);
+            }
+            Stmt::Var(name, initializer, _) => {
+                self.declare_variable(name, &stmt.start);
+                match initializer {
+                    Some(expr) => self.expression(expr),
+                    None => self.emit_op(OpCode::Nil, loc),
+                }
+                self.define_variable(name, &stmt.start);
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, loc);
+                self.emit_op(OpCode::Pop, loc);
+                self.statement(then_branch);
+                let else_jump = self.emit_jump(OpCode::Jump, loc);
+                self.patch_jump(then_jump, &stmt.start);
+                self.emit_op(OpCode::Pop, loc);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump, &stmt.start);
+            }
+            Stmt::While(condition, body) => {
+                let loop_start = self.chunk().code().len();
+                self.expression(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, loc);
+                self.emit_op(OpCode::Pop, loc);
+                self.statement(body);
+                self.emit_loop(loop_start, &stmt.start);
+                self.patch_jump(exit_jump, &stmt.start);
+                self.emit_op(OpCode::Pop, loc);
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.end_scope(stmt.end);
+            }
+            Stmt::Function(declaration) => {
+                self.declare_variable(&declaration.name, &stmt.start);
+                // a function may refer to itself, so it is initialized before its body is compiled
+                self.mark_initialized();
+                self.function(declaration, FunctionKind::Function, stmt);
+                self.define_variable(&declaration.name, &stmt.start);
+            }
+            Stmt::Class(declaration) => self.class(declaration, stmt),
+            Stmt::Return(Some(expr)) => {
+                self.expression(expr);
+                self.emit_op(OpCode::Return, loc);
+            }
+            Stmt::Return(None) => self.emit_return(loc),
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl, kind: FunctionKind, stmt: &LocStmt) {
+        self.states
+            .push(FunctionState::new(&declaration.name, declaration.params.len(), kind));
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare_variable(param, &stmt.start);
+            self.mark_initialized();
+        }
+        for stmt in &declaration.body {
+            self.statement(stmt);
+        }
+        let (function, upvalues) = self.end_function(stmt.end);
+
+        let loc = stmt.start;
+        let function = Value::Function(Rc::new(function));
+        self.emit_with_constant(OpCode::Closure, function, &stmt.start);
+        for upvalue in upvalues {
+            self.emit(upvalue.is_local as u8, loc);
+            self.emit(upvalue.index, loc);
+        }
+    }
+
+    fn class(&mut self, declaration: &ClassDecl, stmt: &LocStmt) {
+        let loc = stmt.start;
+        let name = &declaration.name;
+        self.declare_variable(name, &stmt.start);
+        self.emit_with_name(OpCode::Class, name, &stmt.start);
+        self.define_variable(name, &stmt.start);
+
+        if let Some(superclass) = &declaration.superclass {
+            self.expression(superclass);
+            // `super` lives in its own scope so that every class gets a fresh slot for it
+            self.begin_scope();
+            self.declare_variable("super", &superclass.start);
+            self.mark_initialized();
+            self.variable(name, true, &stmt.start);
+            self.emit_op(OpCode::Inherit, loc);
+        }
+
+        // the class stays on the stack while its methods are bound to it
+        self.variable(name, true, &stmt.start);
+        for method in &declaration.methods {
+            let kind = if method.name == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind, stmt);
+            self.emit_with_name(OpCode::Method, &method.name, &stmt.start);
+        }
+        self.emit_op(OpCode::Pop, loc);
+
+        if declaration.superclass.is_some() {
+            self.end_scope(stmt.end);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, loc: Location) {
+        let state = self.state();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while let Some(local) = self
+            .state()
+            .locals
+            .pop_if(|local| local.depth.is_none_or(|d| d > depth))
+        {
+            if local.is_captured {
+                self.emit_op(OpCode::CloseUpvalue, loc);
+            } else {
+                self.emit_op(OpCode::Pop, loc);
+            }
+        }
+    }
+
+    fn declare_variable(&mut self, name: &str, loc: &Location) {
+        let state = self.state();
+        if state.scope_depth == 0 {
+            return;
+        }
+        if state.locals.len() > u8::MAX as usize {
+            self.report_error_at(loc, "Too many local variables in one function");
+            return;
+        }
+        self.state().locals.push(Local {
+            name: name.to_string(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.state();
+        if state.scope_depth == 0 {
+            return;
+        }
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn define_variable(&mut self, name: &str, loc: &Location) {
+        if self.state().scope_depth > 0 {
+            // the value stays on the stack and becomes the local's slot
+            self.mark_initialized();
+        } else {
+            self.emit_with_name(OpCode::DefineGlobal, name, loc);
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        let locals = &self.states[state].locals;
+        // the parser has made sure that a local isn't read in its own initializer
+        let slot = locals.iter().rposition(|local| local.name == name)?;
+        Some(slot as u8)
+    }
+
+    // looks for the variable in the enclosing functions and threads it through their upvalues
+    fn resolve_upvalue(&mut self, state: usize, name: &str, loc: &Location) -> Option<u8> {
+        if state == 0 {
+            return None;
+        }
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(state, slot, true, loc));
+        }
+        let index = self.resolve_upvalue(state - 1, name, loc)?;
+        Some(self.add_upvalue(state, index, false, loc))
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool, loc: &Location) -> u8 {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing as u8;
+        }
+        if upvalues.len() > u8::MAX as usize {
+            self.report_error_at(loc, "Too many closure variables in function");
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    fn variable(&mut self, name: &str, get: bool, loc: &Location) {
+        let current = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(current, name) {
+            let op = if get { OpCode::GetLocal } else { OpCode::SetLocal };
+            self.emit_op(op, *loc);
+            self.emit(slot, *loc);
+        } else if let Some(index) = self.resolve_upvalue(current, name, loc) {
+            let op = if get { OpCode::GetUpvalue } else { OpCode::SetUpvalue };
+            self.emit_op(op, *loc);
+            self.emit(index, *loc);
+        } else {
+            let op = if get { OpCode::GetGlobal } else { OpCode::SetGlobal };
+            self.emit_with_name(op, name, loc);
+        }
+    }
+
+    // type errors point at the right operand, as in the interpreter
+    fn binary(&mut self, a: &LocExpr, b: &LocExpr, op: OpCode) {
+        self.expression(a);
+        self.expression(b);
+        self.emit_op(op, b.start);
+    }
+
+    // the parts of the target stay on the stack for the store after the operation
+    fn compound(&mut self, target: &LocExpr, op: CompoundOp, value: &LocExpr, expr: &LocExpr) {
+        let loc = expr.start;
+        let op = match op {
+            CompoundOp::Add => OpCode::Add,
+            CompoundOp::Sub => OpCode::Subtract,
+            CompoundOp::Mul => OpCode::Multiply,
+            CompoundOp::Div => OpCode::Divide,
+            CompoundOp::Mod => OpCode::Modulo,
+        };
+        match &target.expr {
+            Expr::Variable(name) => {
+                self.variable(name, true, &target.start);
+                self.expression(value);
+                self.emit_op(op, value.start);
+                self.variable(name, false, &target.start);
+            }
+            Expr::Get(object, name) => {
+                self.expression(object);
+                self.emit_op(OpCode::Dup, loc);
+                self.emit_with_name(OpCode::GetProperty, name, &target.start);
+                self.expression(value);
+                self.emit_op(op, value.start);
+                self.emit_with_name(OpCode::SetProperty, name, &target.start);
+            }
+            Expr::Index(list, index) => {
+                self.expression(list);
+                self.expression(index);
+                self.emit_op(OpCode::Dup2, loc);
+                self.emit_op(OpCode::GetIndex, index.start);
+                self.expression(value);
+                self.emit_op(op, value.start);
+                self.emit_op(OpCode::SetIndex, index.start);
+            }
+            _ => unreachable!("The parser only allows variables, fields and indexes as targets"),
+        }
+    }
+
+    fn expression(&mut self, expr: &LocExpr) {
+        let loc = expr.start;
+        match &expr.expr {
+            Expr::Null => self.emit_op(OpCode::Nil, loc),
+            Expr::Bool(true) => self.emit_op(OpCode::True, loc),
+            Expr::Bool(false) => self.emit_op(OpCode::False, loc),
+            Expr::Number(f) => {
+                self.emit_constant(Value::Number(*f), &expr.start)
+            }
+            Expr::String(s) => {
+                self.emit_constant(Value::String(s.clone()), &expr.start)
+            }
+            Expr::Interpolation(parts) => {
+                if parts.len() > u8::MAX as usize {
+                    self.report_error_at(&expr.start, "Too many parts in one interpolated string");
+                }
+                for part in parts {
+                    self.expression(part);
+                }
+                self.emit_op(OpCode::Interpolate, loc);
+                self.emit(parts.len() as u8, loc);
+            }
+            Expr::Variable(name) => self.variable(name, true, &expr.start),
+            Expr::Assign(name, value) => {
+                self.expression(value);
+                self.variable(name, false, &expr.start);
+            }
+            Expr::Negate(e) => {
+                self.expression(e);
+                self.emit_op(OpCode::Negate, e.start);
+            }
+            Expr::Not(e) => {
+                self.expression(e);
+                self.emit_op(OpCode::Not, loc);
+            }
+            Expr::And(a, b) => {
+                self.expression(a);
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse, loc);
+                self.emit_op(OpCode::Pop, loc);
+                self.expression(b);
+                self.patch_jump(end_jump, &expr.start);
+            }
+            Expr::Or(a, b) => {
+                self.expression(a);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, loc);
+                let end_jump = self.emit_jump(OpCode::Jump, loc);
+                self.patch_jump(else_jump, &expr.start);
+                self.emit_op(OpCode::Pop, loc);
+                self.expression(b);
+                self.patch_jump(end_jump, &expr.start);
+            }
+            Expr::Add(a, b) => self.binary(a, b, OpCode::Add),
+            Expr::Sub(a, b) => self.binary(a, b, OpCode::Subtract),
+            Expr::Mul(a, b) => self.binary(a, b, OpCode::Multiply),
+            Expr::Div(a, b) => self.binary(a, b, OpCode::Divide),
+            Expr::Mod(a, b) => self.binary(a, b, OpCode::Modulo),
+            Expr::Eq(a, b) => self.binary(a, b, OpCode::Equal),
+            Expr::Neq(a, b) => self.binary(a, b, OpCode::NotEqual),
+            Expr::Greater(a, b) => self.binary(a, b, OpCode::Greater),
+            Expr::Less(a, b) => self.binary(a, b, OpCode::Less),
+            Expr::GreaterEqual(a, b) => self.binary(a, b, OpCode::GreaterEqual),
+            Expr::LessEqual(a, b) => self.binary(a, b, OpCode::LessEqual),
+            Expr::Call(callee, args) => {
+                self.expression(callee);
+                for arg in args {
+                    self.expression(arg);
+                }
+                self.emit_op(OpCode::Call, loc);
+                self.emit(args.len() as u8, loc);
+            }
+            Expr::Get(object, name) => {
+                self.expression(object);
+                self.emit_with_name(OpCode::GetProperty, name, &expr.start);
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object);
+                self.expression(value);
+                self.emit_with_name(OpCode::SetProperty, name, &expr.start);
+            }
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+                self.emit_op(OpCode::List, loc);
+                self.emit(elements.len() as u8, loc);
+            }
+            Expr::Index(list, index) => self.binary(list, index, OpCode::GetIndex),
+            Expr::SetIndex(list, index, value) => {
+                self.expression(list);
+                self.expression(index);
+                self.expression(value);
+                self.emit_op(OpCode::SetIndex, index.start);
+            }
+            Expr::Compound(target, op, value) => self.compound(target, *op, value, expr),
+            Expr::This => self.variable("this", true, &expr.start),
+            Expr::Super(name) => {
+                self.variable("this", true, &expr.start);
+                self.variable("super", true, &expr.start);
+                self.emit_with_name(OpCode::GetSuper, name, &expr.start);
+            }
+        }
+    }
+}
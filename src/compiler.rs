@@ -1,132 +1,718 @@
-use crate::expr::Expr;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expr::{CompoundOp, Expr, LocExpr};
 use crate::scanner::{Location, Scanner};
+use crate::stmt::{ClassDecl, FunctionDecl, LocStmt, Stmt};
 use crate::token::{Token, TokenType};
 use std::cmp::PartialEq;
 use std::iter::Peekable;
-use std::vec::IntoIter;
+use std::rc::Rc;
 
-pub struct Parser {
-    had_error: bool,
-    tokens: Peekable<IntoIter<Token>>,
-    pub tree: Expr,
+// Pulls tokens from the scanner as it goes, so the whole token stream never exists at once
+pub struct Parser<'a, I: Iterator<Item = Token<'a>> = Scanner<'a>> {
+    diagnostics: Vec<Diagnostic>,
+    // set after a syntax error until the parser has found the start of the next statement,
+    // errors in between are most likely caused by the first one and are not reported
+    panic_mode: bool,
+    tokens: Peekable<I>,
+    previous_end: Location,
+    function_kind: FunctionKind,
+    class_kind: ClassKind,
+    // doc comment lines in front of the next token
+    doc: Vec<&'a str>,
+    // the locals of each enclosing block and function and whether their initializer is
+    // done, empty at the top level where variables are global
+    scopes: Vec<Vec<(String, bool)>>,
+    tree: Vec<LocStmt>,
 }
 
-impl Parser {
-    pub fn new(s: &str) -> Self {
-        let mut scanner = Scanner::new(s);
-        scanner.lex();
+impl<'a> Parser<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Parser::from_tokens(Scanner::new(s))
+    }
+}
+
+impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
+    // parses tokens that were scanned separately, scanner errors are still reported from the tokens
+    pub fn from_tokens(tokens: impl IntoIterator<Item = Token<'a>, IntoIter = I>) -> Self {
         Parser {
-            had_error: false,
-            tokens: scanner.tokens.into_iter().peekable(),
-            tree: Expr::Null,
+            diagnostics: Vec::new(),
+            panic_mode: false,
+            tokens: tokens.into_iter().peekable(),
+            previous_end: Location::START,
+            function_kind: FunctionKind::Script,
+            class_kind: ClassKind::None,
+            doc: Vec::new(),
+            scopes: Vec::new(),
+            tree: Vec::new(),
+        }
+    }
+
+    // errors that don't confuse the parser, like a misplaced 'return'
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if !self.panic_mode {
+            self.diagnostics.push(diagnostic);
         }
     }
 
-    fn report_error_at(&mut self, loc: &Location, message: &str) {
-        eprintln!("[parser] {} in line {}, at {}", message, loc.line, loc.col);
-        self.had_error = true;
+    fn report_error_at(&mut self, span: Span, message: &str) {
+        self.report(Diagnostic::error(message, span));
     }
 
-    fn report_scanner_error(&mut self, loc: &Location, message: &str) {
-        eprintln!("[scanner] {} in line {}, at {}", loc.line, loc.col, message);
-        self.had_error = true;
+    // errors after which the parser can't tell where it is anymore
+    fn report_syntax_error(&mut self, diagnostic: Diagnostic) {
+        self.report(diagnostic);
+        self.panic_mode = true;
+    }
+
+    fn report_unexpected_token(&mut self, token: &Token, message: &str) {
+        let span = Span::new(token.start, token.end);
+        self.report_syntax_error(
+            Diagnostic::error(message, span).with_primary_label(format!("found '{}'", token)),
+        );
+    }
+
+    fn report_unexpected_next(&mut self, message: &str) {
+        match self.peek_token() {
+            Some(token) => {
+                let diagnostic = Diagnostic::error(message, Span::new(token.start, token.end))
+                    .with_primary_label(format!("found '{}'", token));
+                self.report_syntax_error(diagnostic);
+            }
+            None => self.report_error_at_end(message),
+        }
     }
 
     fn report_error_at_end(&mut self, message: &str) {
-        eprintln!("[parser] {}", message);
-        self.had_error = true;
+        let span = Span::point(self.previous_end);
+        self.report_syntax_error(
+            Diagnostic::error(message, span).with_primary_label("found end of file"),
+        );
     }
 
-    pub fn compile(&mut self) -> bool {
-        self.tree = self.expression();
-        println!("{:?}", self.tree);
-        !self.had_error
+    // Parses the whole program, returns every independent error if there were any
+    pub fn compile(mut self) -> Result<Vec<LocStmt>, Vec<Diagnostic>> {
+        while self.peek_token().is_some() {
+            let stmt = self.declaration();
+            self.tree.push(stmt);
+        }
+        if self.diagnostics.is_empty() {
+            Ok(self.tree)
+        } else {
+            Err(self.diagnostics)
+        }
     }
 
-    fn expression(&mut self) -> Expr {
-        self.parse_precedence(Precedence::Assignment)
+    fn declaration(&mut self) -> LocStmt {
+        let start = self.peek_start();
+        let doc = self.take_doc();
+        let stmt = if self.match_token_type(TokenType::Var) {
+            let stmt = self.var_declaration(doc);
+            LocStmt::new(stmt, start, self.previous_end)
+        } else if self.match_token_type(TokenType::Fun) {
+            let stmt = Stmt::Function(Rc::new(self.function(FunctionKind::Function, doc)));
+            LocStmt::new(stmt, start, self.previous_end)
+        } else if self.match_token_type(TokenType::Class) {
+            let stmt = self.class_declaration(doc);
+            LocStmt::new(stmt, start, self.previous_end)
+        } else {
+            self.statement()
+        };
+        if self.panic_mode {
+            self.synchronize();
+        }
+        stmt
     }
 
-    fn parse_precedence(&mut self, precedence: Precedence) -> Expr {
-        let mut expr = Expr::Null;
-        if let Some(token) = self.next_token() {
-            if let Some(ex) = self.parse_prefix(token.token_type) {
-                expr = ex;
+    // skips tokens until a statement boundary: just after a ';' or before a statement keyword or '}'
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+        while let Some(token) = self.peek_token() {
+            match token.token_type {
+                TokenType::Semicolon => {
+                    self.next_token();
+                    return;
+                }
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                // stopping at a '}' lets the enclosing block end where it was meant to
+                | TokenType::RightBrace => return,
+                _ => {
+                    self.next_token();
+                }
+            }
+        }
+    }
+
+    fn var_declaration(&mut self, doc: Option<String>) -> Stmt {
+        let name_start = self.peek_start();
+        let name = self.expect_identifier("Expected variable name");
+        self.declare_local(&name, Span::new(name_start, self.previous_end), false);
+        let initializer = if self.match_token_type(TokenType::Equal) {
+            Some(self.expression())
+        } else {
+            None
+        };
+        if let Some((_, initialized)) = self.scopes.last_mut().and_then(|scope| scope.last_mut()) {
+            *initialized = true;
+        }
+        self.expect_token_type(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration",
+        );
+        Stmt::Var(name, initializer, doc)
+    }
+
+    fn class_declaration(&mut self, doc: Option<String>) -> Stmt {
+        let name_start = self.peek_start();
+        let name = self.expect_identifier("Expected class name");
+        let name_span = Span::new(name_start, self.previous_end);
+        self.declare_local(&name, name_span, true);
+        let superclass = if self.match_token_type(TokenType::Less) {
+            let start = self.peek_start();
+            let superclass = self.expect_identifier("Expected superclass name");
+            if superclass == name {
+                let span = Span::new(start, self.previous_end);
+                self.report(
+                    Diagnostic::error("A class can't inherit from itself", span)
+                        .with_label(name_span, "class declared here"),
+                );
+            }
+            Some(LocExpr::new(Expr::Variable(superclass), start, self.previous_end))
+        } else {
+            None
+        };
+        let enclosing_class = self.class_kind;
+        self.class_kind = if superclass.is_some() {
+            ClassKind::Subclass
+        } else {
+            ClassKind::Class
+        };
+
+        self.expect_token_type(TokenType::LeftBrace, "Expected '{' before class body");
+        let mut methods = Vec::new();
+        while self
+            .peek_token()
+            .is_some_and(|tk| tk.token_type != TokenType::RightBrace)
+        {
+            let doc = self.take_doc();
+            let is_initializer = self
+                .peek_token()
+                .is_some_and(|tk| tk.token_type == TokenType::Identifier("init".into()));
+            let kind = if is_initializer {
+                FunctionKind::Initializer
             } else {
-                self.report_error_at(
-                    &token.start,
-                    "Expected Constant or Infix operator, found smth else. Good luck finding it :)",
+                FunctionKind::Method
+            };
+            methods.push(Rc::new(self.function(kind, doc)));
+        }
+        self.expect_token_type(TokenType::RightBrace, "Expected '}' after class body");
+
+        self.class_kind = enclosing_class;
+        Stmt::Class(ClassDecl {
+            name,
+            superclass,
+            methods,
+            doc,
+        })
+    }
+
+    fn function(&mut self, kind: FunctionKind, doc: Option<String>) -> FunctionDecl {
+        let name_start = self.peek_start();
+        let name = self.expect_identifier("Expected function name");
+        // methods are looked up on the class, they don't declare a variable
+        if kind == FunctionKind::Function {
+            self.declare_local(&name, Span::new(name_start, self.previous_end), true);
+        }
+        self.expect_token_type(TokenType::LeftParenthesis, "Expected '(' after function name");
+        self.scopes.push(Vec::new());
+        let mut params = Vec::new();
+        if self
+            .peek_token()
+            .is_some_and(|tk| tk.token_type != TokenType::RightParenthesis)
+        {
+            loop {
+                if params.len() == 255 {
+                    let span = self.peek_span();
+                    self.report_error_at(span, "Can't have more than 255 parameters");
+                }
+                let param_start = self.peek_start();
+                let param = self.expect_identifier("Expected parameter name");
+                self.declare_local(&param, Span::new(param_start, self.previous_end), true);
+                params.push(param);
+                if !self.match_token_type(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect_token_type(TokenType::RightParenthesis, "Expected ')' after parameters");
+        self.expect_token_type(TokenType::LeftBrace, "Expected '{' before function body");
+        let enclosing_function = std::mem::replace(&mut self.function_kind, kind);
+        let body = self.block();
+        self.scopes.pop();
+        self.function_kind = enclosing_function;
+        FunctionDecl {
+            name,
+            params,
+            body,
+            doc,
+        }
+    }
+
+    fn statement(&mut self) -> LocStmt {
+        let start = self.peek_start();
+        let stmt = if self.match_token_type(TokenType::Print) {
+            let expr = self.expression();
+            self.expect_token_type(TokenType::Semicolon, "Expected ';' after value");
+            Stmt::Print(expr)
+        } else if self.match_token_type(TokenType::LeftBrace) {
+            self.scopes.push(Vec::new());
+            let block = self.block();
+            self.scopes.pop();
+            Stmt::Block(block)
+        } else if self.match_token_type(TokenType::If) {
+            self.if_statement()
+        } else if self.match_token_type(TokenType::While) {
+            self.while_statement()
+        } else if self.match_token_type(TokenType::For) {
+            self.for_statement(start)
+        } else if self.match_token_type(TokenType::Return) {
+            self.return_statement(start)
+        } else {
+            self.expression_statement()
+        };
+        LocStmt::new(stmt, start, self.previous_end)
+    }
+
+    fn expression_statement(&mut self) -> Stmt {
+        let expr = self.expression();
+        self.expect_token_type(TokenType::Semicolon, "Expected ';' after expression");
+        Stmt::Expression(expr)
+    }
+
+    fn return_statement(&mut self, start: Location) -> Stmt {
+        if self.function_kind == FunctionKind::Script {
+            let span = Span::new(start, self.previous_end);
+            self.report_error_at(span, "Can't return from top-level code");
+        }
+        let value = if self
+            .peek_token()
+            .is_some_and(|tk| tk.token_type == TokenType::Semicolon)
+        {
+            None
+        } else {
+            let value = self.expression();
+            if self.function_kind == FunctionKind::Initializer {
+                self.report(
+                    Diagnostic::error("Can't return a value from an initializer", value.span())
+                        .with_help("initializers always return the instance, use a bare 'return;'"),
                 );
             }
+            Some(value)
+        };
+        self.expect_token_type(TokenType::Semicolon, "Expected ';' after return value");
+        Stmt::Return(value)
+    }
+
+    fn if_statement(&mut self) -> Stmt {
+        self.expect_token_type(TokenType::LeftParenthesis, "Expected '(' after 'if'");
+        let condition = self.expression();
+        self.expect_token_type(TokenType::RightParenthesis, "Expected ')' after condition");
+        let then_branch = self.statement();
+        let else_branch = if self.match_token_type(TokenType::Else) {
+            Some(Box::new(self.statement()))
         } else {
-            self.report_error_at_end("Expected expression, but found EOF.");
+            None
+        };
+        Stmt::If(condition, Box::new(then_branch), else_branch)
+    }
+
+    fn while_statement(&mut self) -> Stmt {
+        self.expect_token_type(TokenType::LeftParenthesis, "Expected '(' after 'while'");
+        let condition = self.expression();
+        self.expect_token_type(TokenType::RightParenthesis, "Expected ')' after condition");
+        let body = self.statement();
+        Stmt::While(condition, Box::new(body))
+    }
+
+    // for loops have no node of their own, they are desugared into a while loop
+    fn for_statement(&mut self, start: Location) -> Stmt {
+        self.expect_token_type(TokenType::LeftParenthesis, "Expected '(' after 'for'");
+        // the loop variable is scoped to the loop
+        self.scopes.push(Vec::new());
+        let initializer_start = self.peek_start();
+        let initializer = if self.match_token_type(TokenType::Semicolon) {
+            None
+        } else if self.match_token_type(TokenType::Var) {
+            let stmt = self.var_declaration(None);
+            Some(LocStmt::new(stmt, initializer_start, self.previous_end))
+        } else {
+            let stmt = self.expression_statement();
+            Some(LocStmt::new(stmt, initializer_start, self.previous_end))
+        };
+
+        let condition = if self.peek_token().is_some_and(|tk| tk.token_type == TokenType::Semicolon) {
+            LocExpr::new(Expr::Bool(true), self.previous_end, self.previous_end)
+        } else {
+            self.expression()
+        };
+        self.expect_token_type(TokenType::Semicolon, "Expected ';' after loop condition");
+
+        let increment = if self
+            .peek_token()
+            .is_some_and(|tk| tk.token_type == TokenType::RightParenthesis)
+        {
+            None
+        } else {
+            Some(self.expression())
+        };
+        self.expect_token_type(TokenType::RightParenthesis, "Expected ')' after for clauses");
+
+        let mut body = self.statement();
+        if let Some(increment) = increment {
+            let (body_start, body_end) = (body.start, body.end);
+            let (increment_start, increment_end) = (increment.start, increment.end);
+            let increment = LocStmt::new(Stmt::Expression(increment), increment_start, increment_end);
+            body = LocStmt::new(Stmt::Block(vec![body, increment]), body_start, body_end);
+        }
+        let mut stmt = Stmt::While(condition, Box::new(body));
+        if let Some(initializer) = initializer {
+            let looped = LocStmt::new(stmt, start, self.previous_end);
+            stmt = Stmt::Block(vec![initializer, looped]);
+        }
+        self.scopes.pop();
+        stmt
+    }
+
+    // a new local in the innermost scope, globals may be declared again
+    fn declare_local(&mut self, name: &str, span: Span, initialized: bool) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        let declared = scope.iter().any(|(local, _)| local == name);
+        scope.push((name.to_string(), initialized));
+        if declared {
+            self.report_error_at(span, "Already a variable with this name in this scope");
+        }
+    }
+
+    fn variable(&mut self, name: String, span: Span) -> Expr {
+        let local = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|(local, _)| *local == name));
+        if let Some((_, false)) = local {
+            self.report_error_at(span, "Can't read local variable in its own initializer");
+        }
+        Expr::Variable(name)
+    }
+
+    fn block(&mut self) -> Vec<LocStmt> {
+        let mut statements = Vec::new();
+        while self
+            .peek_token()
+            .is_some_and(|tk| tk.token_type != TokenType::RightBrace)
+        {
+            statements.push(self.declaration());
+        }
+        self.expect_token_type(TokenType::RightBrace, "Expected '}' after block");
+        statements
+    }
+
+    fn expression(&mut self) -> LocExpr {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> LocExpr {
+        let mut expr = LocExpr::new(Expr::Null, self.previous_end, self.previous_end);
+        if let Some(token) = self.next_token() {
+            let start = token.start;
+            match self.parse_prefix(token) {
+                Ok(ex) => expr = LocExpr::new(ex, start, self.previous_end),
+                Err(token) => self.report_unexpected_token(&token, "Expected expression"),
+            }
+        } else {
+            self.report_error_at_end("Expected expression");
         }
         while let Some(token) =
             self.next_token_if(|tk| get_rule(&tk.token_type).infix >= precedence)
         {
-            if let Some(infix) = self.parse_infix(token.token_type, expr) {
-                expr = infix;
-            } else {
-                self.report_error_at(&token.start, "Unimplemented token");
-                expr = Expr::Null;
+            let start = expr.start;
+            match self.parse_infix(token, expr) {
+                Ok(infix) => expr = LocExpr::new(infix, start, self.previous_end),
+                Err(token) => {
+                    self.report_unexpected_token(&token, "Unimplemented operator");
+                    expr = LocExpr::new(Expr::Null, start, self.previous_end);
+                }
             }
         }
         expr
     }
     // 53550127
 
-    fn next_token_if(&mut self, func: impl Fn(&Token) -> bool) -> Option<Token> {
-        self.tokens.next_if(func)
+    // reports and drops any invalid tokens and sets doc comments aside, so the parser only
+    // ever sees valid ones
+    fn skip_invalid_tokens(&mut self) {
+        while let Some(token) = self.tokens.next_if(|tk| {
+            matches!(tk.token_type, TokenType::Invalid(_) | TokenType::DocComment(_))
+        }) {
+            match token.token_type {
+                TokenType::DocComment(text) => self.doc.push(text),
+                TokenType::Invalid(msg) => {
+                    // the scanner already knows exactly what went wrong, so this is never a follow-up error
+                    self.diagnostics
+                        .push(Diagnostic::error(msg, Span::new(token.start, token.end)));
+                    self.panic_mode = true;
+                }
+                _ => unreachable!(),
+            }
+        }
     }
 
-    fn next_token(&mut self) -> Option<Token> {
-        loop {
-            match self.tokens.next() {
-                Some(Token {
-                    start,
-                    end: _,
-                    token_type: TokenType::Invalid(msg),
-                }) => self.report_scanner_error(&start, msg.as_str()),
-                Some(tk) => return Some(tk),
-                None => return None,
-            }
+    // the doc comment in front of the next token, if it starts a declaration
+    fn take_doc(&mut self) -> Option<String> {
+        self.skip_invalid_tokens();
+        if self.doc.is_empty() {
+            return None;
         }
+        let doc = self.doc.join("\n");
+        self.doc.clear();
+        Some(doc)
     }
 
+    fn peek_token(&mut self) -> Option<&Token<'a>> {
+        self.skip_invalid_tokens();
+        self.tokens.peek()
+    }
+
+    fn peek_start(&mut self) -> Location {
+        let end = self.previous_end;
+        self.peek_token().map_or(end, |tk| tk.start)
+    }
+
+    // the span of the next token, or the end of the input if there is none
+    fn peek_span(&mut self) -> Span {
+        let end = self.previous_end;
+        self.peek_token()
+            .map_or(Span::point(end), |tk| Span::new(tk.start, tk.end))
+    }
+
+    fn next_token_if(&mut self, func: impl Fn(&Token) -> bool) -> Option<Token<'a>> {
+        self.skip_invalid_tokens();
+        let token = self.tokens.next_if(func)?;
+        // doc comments in front of anything but a declaration document nothing
+        self.doc.clear();
+        self.previous_end = token.end;
+        Some(token)
+    }
+
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        self.skip_invalid_tokens();
+        let token = self.tokens.next()?;
+        self.doc.clear();
+        self.previous_end = token.end;
+        Some(token)
+    }
+
+    fn match_token_type(&mut self, typ: TokenType) -> bool {
+        self.next_token_if(|tk| tk.token_type == typ).is_some()
+    }
+
+    // a mismatching token is left in place, it might well start the next statement
     fn expect_token_type(&mut self, typ: TokenType, msg: &str) {
-        if let Some(tok) = self.next_token() {
-            if tok.token_type != typ {
-                self.had_error = true;
-                self.report_error_at(&tok.start, msg);
+        if !self.match_token_type(typ) {
+            self.report_unexpected_next(msg);
+        }
+    }
+
+    fn expect_identifier(&mut self, msg: &str) -> String {
+        match self.next_token_if(|tk| matches!(tk.token_type, TokenType::Identifier(_))) {
+            Some(Token {
+                token_type: TokenType::Identifier(name),
+                ..
+            }) => name.into_owned(),
+            _ => {
+                self.report_unexpected_next(msg);
+                String::new()
             }
-        } else {
-            self.had_error = true;
         }
     }
 
     // prefix operators and constants: anything that doesn't need the expr that came before
-    fn parse_prefix(&mut self, tkt: TokenType) -> Option<Expr> {
-        let expr = match tkt {
+    // hands the token back if it can't start an expression
+    fn parse_prefix(&mut self, token: Token<'a>) -> Result<Expr, Token<'a>> {
+        let start = token.start;
+        let expr = match token.token_type {
+            TokenType::This => {
+                if self.class_kind == ClassKind::None {
+                    let span = Span::new(start, self.previous_end);
+                    self.report_error_at(span, "Can't use 'this' outside of a class");
+                }
+                Expr::This
+            }
+            TokenType::Super => {
+                let span = Span::new(start, self.previous_end);
+                match self.class_kind {
+                    ClassKind::None => {
+                        self.report_error_at(span, "Can't use 'super' outside of a class")
+                    }
+                    ClassKind::Class => self.report_error_at(
+                        span,
+                        "Can't use 'super' in a class with no superclass",
+                    ),
+                    ClassKind::Subclass => {}
+                }
+                self.expect_token_type(TokenType::Dot, "Expected '.' after 'super'");
+                Expr::Super(self.expect_identifier("Expected superclass method name"))
+            }
             TokenType::LeftParenthesis => {
                 let ex = self.expression();
                 self.expect_token_type(TokenType::RightParenthesis, "Expected ')'");
-                ex
+                ex.expr
             }
             TokenType::NumberLiteral(f) => Expr::Number(f),
-            TokenType::StringLiteral(s) => Expr::String(s),
+            TokenType::StringLiteral(s) => Expr::String(s.into_owned()),
+            TokenType::StringInterpolation(s) => {
+                self.interpolation(s.into_owned(), start, token.end)
+            }
+            TokenType::Identifier(name) => {
+                self.variable(name.into_owned(), Span::new(start, token.end))
+            }
             TokenType::Nil => Expr::Null,
             TokenType::True => Expr::Bool(true),
             TokenType::False => Expr::Bool(false),
             TokenType::Minus => Expr::Negate(Box::new(self.parse_precedence(Precedence::Unary))),
-            _ => return None,
+            TokenType::Bang => Expr::Not(Box::new(self.parse_precedence(Precedence::Unary))),
+            TokenType::LeftBracket => {
+                let mut elements = Vec::new();
+                if self
+                    .peek_token()
+                    .is_some_and(|tk| tk.token_type != TokenType::RightBracket)
+                {
+                    loop {
+                        if elements.len() == 255 {
+                            let span = self.peek_span();
+                            self.report_error_at(span, "Can't have more than 255 elements in a list literal");
+                        }
+                        elements.push(self.expression());
+                        if !self.match_token_type(TokenType::Comma) {
+                            break;
+                        }
+                    }
+                }
+                self.expect_token_type(TokenType::RightBracket, "Expected ']' after list elements");
+                Expr::List(elements)
+            }
+            token_type => {
+                return Err(Token {
+                    token_type,
+                    ..token
+                })
+            }
         };
 
-        Some(expr)
+        Ok(expr)
+    }
+
+    // `"a ${b} c"` arrives as StringInterpolation("a "), the tokens of b, StringLiteral(" c")
+    fn interpolation(&mut self, text: String, start: Location, end: Location) -> Expr {
+        let mut parts = vec![LocExpr::new(Expr::String(text), start, end)];
+        loop {
+            parts.push(self.expression());
+            let next = self.next_token_if(|tk| {
+                matches!(
+                    tk.token_type,
+                    TokenType::StringInterpolation(_) | TokenType::StringLiteral(_)
+                )
+            });
+            match next {
+                Some(Token {
+                    token_type: TokenType::StringInterpolation(text),
+                    start,
+                    end,
+                    ..
+                }) => parts.push(LocExpr::new(Expr::String(text.into_owned()), start, end)),
+                Some(Token {
+                    token_type: TokenType::StringLiteral(text),
+                    start,
+                    end,
+                    ..
+                }) => {
+                    parts.push(LocExpr::new(Expr::String(text.into_owned()), start, end));
+                    break;
+                }
+                _ => {
+                    self.report_unexpected_next("Expected '}' after interpolated expression");
+                    break;
+                }
+            }
+        }
+        Expr::Interpolation(parts)
     }
 
     // infix, mixfix and postfix operators: They need access to the expr before
-    fn parse_infix(&mut self, tkt: TokenType, lhs: Expr) -> Option<Expr> {
-        let expr = match tkt {
+    fn parse_infix(&mut self, token: Token<'a>, lhs: LocExpr) -> Result<Expr, Token<'a>> {
+        let expr = match token.token_type {
+            TokenType::Dot => {
+                let name = self.expect_identifier("Expected property name after '.'");
+                Expr::Get(Box::new(lhs), name)
+            }
+            TokenType::LeftParenthesis => {
+                let mut args = Vec::new();
+                if self
+                    .peek_token()
+                    .is_some_and(|tk| tk.token_type != TokenType::RightParenthesis)
+                {
+                    loop {
+                        if args.len() == 255 {
+                            let span = self.peek_span();
+                            self.report_error_at(span, "Can't have more than 255 arguments");
+                        }
+                        args.push(self.expression());
+                        if !self.match_token_type(TokenType::Comma) {
+                            break;
+                        }
+                    }
+                }
+                self.expect_token_type(TokenType::RightParenthesis, "Expected ')' after arguments");
+                Expr::Call(Box::new(lhs), args)
+            }
+            TokenType::LeftBracket => {
+                let index = self.expression();
+                self.expect_token_type(TokenType::RightBracket, "Expected ']' after index");
+                Expr::Index(Box::new(lhs), Box::new(index))
+            }
+            TokenType::Equal => {
+                let rhs = self.parse_precedence(Precedence::Assignment);
+                match lhs.expr {
+                    Expr::Variable(name) => Expr::Assign(name, Box::new(rhs)),
+                    Expr::Get(object, name) => Expr::Set(object, name, Box::new(rhs)),
+                    Expr::Index(list, index) => Expr::SetIndex(list, index, Box::new(rhs)),
+                    _ => {
+                        self.report_error_at(lhs.span(), "Invalid assignment target");
+                        Expr::Null
+                    }
+                }
+            }
+            TokenType::PlusEqual => self.compound(lhs, CompoundOp::Add),
+            TokenType::MinusEqual => self.compound(lhs, CompoundOp::Sub),
+            TokenType::StarEqual => self.compound(lhs, CompoundOp::Mul),
+            TokenType::SlashEqual => self.compound(lhs, CompoundOp::Div),
+            TokenType::ModuloEqual => self.compound(lhs, CompoundOp::Mod),
+            TokenType::Or => {
+                let rhs = self.parse_precedence(Precedence::And);
+                Expr::Or(Box::new(lhs), Box::new(rhs))
+            }
+            TokenType::And => {
+                let rhs = self.parse_precedence(Precedence::Equality);
+                Expr::And(Box::new(lhs), Box::new(rhs))
+            }
             TokenType::Slash => {
                 let rhs = self.parse_precedence(Precedence::Unary);
                 Expr::Div(Box::new(lhs), Box::new(rhs))
@@ -148,11 +734,11 @@ impl Parser {
                 Expr::Mod(Box::new(lhs), Box::new(rhs))
             }
             TokenType::BangEqual => {
-                let rhs = self.parse_precedence(Precedence::Term);
+                let rhs = self.parse_precedence(Precedence::Comparison);
                 Expr::Neq(Box::new(lhs), Box::new(rhs))
             }
             TokenType::EqualEqual => {
-                let rhs = self.parse_precedence(Precedence::Term);
+                let rhs = self.parse_precedence(Precedence::Comparison);
                 Expr::Eq(Box::new(lhs), Box::new(rhs))
             }
             TokenType::Greater => {
@@ -171,12 +757,46 @@ impl Parser {
                 let rhs = self.parse_precedence(Precedence::Term);
                 Expr::LessEqual(Box::new(lhs), Box::new(rhs))
             }
-            _ => return None,
+            token_type => {
+                return Err(Token {
+                    token_type,
+                    ..token
+                })
+            }
         };
-        Some(expr)
+        Ok(expr)
+    }
+
+    // `target op= value` for everything that can be assigned to
+    fn compound(&mut self, target: LocExpr, op: CompoundOp) -> Expr {
+        let rhs = self.parse_precedence(Precedence::Assignment);
+        match target.expr {
+            Expr::Variable(_) | Expr::Get(..) | Expr::Index(..) => {
+                Expr::Compound(Box::new(target), op, Box::new(rhs))
+            }
+            _ => {
+                self.report_error_at(target.span(), "Invalid assignment target");
+                Expr::Null
+            }
+        }
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 enum Precedence {
     None,
@@ -192,6 +812,7 @@ enum Precedence {
     Primary,
 }
 
+#[allow(dead_code)]
 struct Rule {
     prefix: Precedence,
     infix: Precedence,
@@ -200,8 +821,8 @@ struct Rule {
 const fn get_rule(tkt: &TokenType) -> Rule {
     match tkt {
         TokenType::LeftBracket => Rule {
-            prefix: Precedence::None,
-            infix: Precedence::None,
+            prefix: Precedence::Primary,
+            infix: Precedence::Call,
         },
         TokenType::RightBracket => Rule {
             prefix: Precedence::None,
@@ -217,7 +838,7 @@ const fn get_rule(tkt: &TokenType) -> Rule {
         },
         TokenType::LeftParenthesis => Rule {
             prefix: Precedence::Primary,
-            infix: Precedence::None,
+            infix: Precedence::Call,
         },
         TokenType::RightParenthesis => Rule {
             prefix: Precedence::None,
@@ -229,7 +850,7 @@ const fn get_rule(tkt: &TokenType) -> Rule {
         },
         TokenType::Dot => Rule {
             prefix: Precedence::None,
-            infix: Precedence::None,
+            infix: Precedence::Call,
         },
         TokenType::Minus => Rule {
             prefix: Precedence::Unary,
@@ -252,20 +873,28 @@ const fn get_rule(tkt: &TokenType) -> Rule {
             infix: Precedence::Factor,
         },
         TokenType::Bang => Rule {
-            prefix: Precedence::None,
+            prefix: Precedence::Unary,
             infix: Precedence::None,
         },
         TokenType::BangEqual => Rule {
             prefix: Precedence::None,
-            infix: Precedence::Comparison,
+            infix: Precedence::Equality,
         },
         TokenType::Equal => Rule {
             prefix: Precedence::None,
-            infix: Precedence::None,
+            infix: Precedence::Assignment,
+        },
+        TokenType::PlusEqual
+        | TokenType::MinusEqual
+        | TokenType::StarEqual
+        | TokenType::SlashEqual
+        | TokenType::ModuloEqual => Rule {
+            prefix: Precedence::None,
+            infix: Precedence::Assignment,
         },
         TokenType::EqualEqual => Rule {
             prefix: Precedence::None,
-            infix: Precedence::Comparison,
+            infix: Precedence::Equality,
         },
         TokenType::Greater => Rule {
             prefix: Precedence::None,
@@ -291,13 +920,17 @@ const fn get_rule(tkt: &TokenType) -> Rule {
             prefix: Precedence::None,
             infix: Precedence::None,
         },
+        TokenType::StringInterpolation(_) => Rule {
+            prefix: Precedence::None,
+            infix: Precedence::None,
+        },
         TokenType::NumberLiteral(_) => Rule {
             prefix: Precedence::None,
             infix: Precedence::None,
         },
         TokenType::And => Rule {
             prefix: Precedence::None,
-            infix: Precedence::None,
+            infix: Precedence::And,
         },
         TokenType::Class => Rule {
             prefix: Precedence::None,
@@ -329,7 +962,7 @@ const fn get_rule(tkt: &TokenType) -> Rule {
         },
         TokenType::Or => Rule {
             prefix: Precedence::None,
-            infix: Precedence::None,
+            infix: Precedence::Or,
         },
         TokenType::Print => Rule {
             prefix: Precedence::None,
@@ -363,7 +996,7 @@ const fn get_rule(tkt: &TokenType) -> Rule {
             prefix: Precedence::None,
             infix: Precedence::Factor,
         },
-        TokenType::Invalid(_) => {
+        TokenType::DocComment(_) | TokenType::Invalid(_) => {
             panic!("Invalid token")
         }
     }
@@ -1,5 +1,5 @@
-use crate::chunk::{Chunk};
-use crate::chunk::opcode::*;
+use crate::chunk::{Chunk, OpCode};
+use crate::value::Value;
 
 pub fn print_chunk(chunk: &Chunk, name: &str) {
     println!("=== {} ===", name);
@@ -12,47 +12,70 @@ pub fn print_chunk(chunk: &Chunk, name: &str) {
 
 pub fn print_instruction(chunk: &Chunk, offset: usize) -> usize {
     let val = chunk.code()[offset];
-    let line = chunk.lines()[offset];
+    let line = chunk.location_at(offset).line;
 
-    if offset > 0 && line == chunk.lines()[offset - 1] {
+    if offset > 0 && line == chunk.location_at(offset - 1).line {
         print!("{:04}    | ", offset);
     } else {
         print!("{:04} {:>4} ", offset, line);
     }
 
 
-    match val {
-        RETURN => simple_instruction("RETURN", offset),
-        CONSTANT => constant_instruction("CONSTANT", chunk, offset),
-        NEGATE => simple_instruction("NEGATE", offset),
-        ADD => simple_instruction("ADD", offset),
-        SUBTRACT => simple_instruction("SUBTRACT", offset),
-        MULTIPLY => simple_instruction("MULTIPLY", offset),
-        DIVIDE => simple_instruction("DIVIDE", offset),
-        MODULO => simple_instruction("MODULO", offset),
-        GREATER => simple_instruction("GREATER", offset),
-        GREATER_EQUAL => simple_instruction("GREATER_EQUAL", offset),
-        LESS => simple_instruction("LESS", offset),
-        LESS_EQUAL => simple_instruction("LESS_EQUAL", offset),
-        EQUAL => simple_instruction("EQUAL", offset),
-        NOT_EQUAL => simple_instruction("NOT_EQUAL", offset),
-        _ => simple_instruction("UNRECOGNIZED INSTRUCTION", offset),
+    let Ok(op) = OpCode::try_from(val) else {
+        println!("UNRECOGNIZED INSTRUCTION {}", val);
+        return offset + 1;
+    };
+    let name = op.name();
+    let operand = chunk.read_operand(offset + 1, op.operand_width());
+    let next = offset + 1 + op.operand_width();
+    match op {
+        OpCode::Constant | OpCode::ConstantLong => {
+            println!("{:<16} {}: {}", name, operand, chunk.constants()[operand]);
+        }
+        OpCode::DefineGlobal
+        | OpCode::DefineGlobalLong
+        | OpCode::GetGlobal
+        | OpCode::GetGlobalLong
+        | OpCode::SetGlobal
+        | OpCode::SetGlobalLong
+        | OpCode::Class
+        | OpCode::ClassLong
+        | OpCode::GetProperty
+        | OpCode::GetPropertyLong
+        | OpCode::SetProperty
+        | OpCode::SetPropertyLong
+        | OpCode::Method
+        | OpCode::MethodLong
+        | OpCode::GetSuper
+        | OpCode::GetSuperLong => println!("{:<16} {}: {}", name, operand, chunk.names()[operand]),
+        OpCode::Jump | OpCode::JumpIfFalse => {
+            println!("{:<16} {} -> {}", name, offset, next + operand);
+        }
+        OpCode::Loop => println!("{:<16} {} -> {}", name, offset, next - operand),
+        OpCode::Closure | OpCode::ClosureLong => {
+            return closure_instruction(name, operand, chunk, next);
+        }
+        _ if op.operand_width() == 0 => println!("{}", name),
+        _ => println!("{:<16} {}", name, operand),
     }
+    next
 }
 
-fn simple_instruction(name: &str, offset: usize) -> usize {
-    println!("{}", name);
-    offset + 1
-}
-
-fn constant_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
+// the function is followed by where each of its upvalues is captured from
+fn closure_instruction(name: &str, index: usize, chunk: &Chunk, offset: usize) -> usize {
     let code = chunk.code();
-    let index = code[offset + 1];
-    println!(
-        "{:<16} {}: {}",
-        name,
-        index,
-        chunk.constants()[index as usize]
-    );
-    offset + 2
+    let constant = &chunk.constants()[index];
+    println!("{:<16} {}: {}", name, index, constant);
+
+    let upvalue_count = match constant {
+        Value::Function(function) => function.upvalue_count,
+        _ => 0,
+    };
+    let mut offset = offset;
+    for _ in 0..upvalue_count {
+        let kind = if code[offset] == 1 { "local" } else { "upvalue" };
+        println!("{:04}    |                     {} {}", offset, kind, code[offset + 1]);
+        offset += 2;
+    }
+    offset
 }
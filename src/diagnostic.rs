@@ -0,0 +1,228 @@
+use crate::scanner::Location;
+use std::io::IsTerminal;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+// calls listed under a runtime error, after collapsing repeated ones
+const MAX_TRACE_NOTES: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    #[allow(dead_code)]
+    Warning,
+}
+
+// A stretch of source code, the end is exclusive
+#[derive(Debug, Copy, Clone)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub fn new(start: Location, end: Location) -> Self {
+        Span { start, end }
+    }
+
+    // the bytes of the source that the span covers
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.start.index..self.end.index
+    }
+
+    // a span covering just the character at the location
+    pub fn point(location: Location) -> Self {
+        let mut end = location;
+        end.col += 1;
+        end.index += 1;
+        Span { start: location, end }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// Everything that is reported to the user about their code goes through this,
+// no matter if it was found while scanning, parsing, generating code or running
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // text shown next to the carets under the primary span
+    pub primary_label: Option<String>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            primary_label: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Self {
+        self.primary_label = Some(message.into());
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    // one note per call that led to a runtime error, innermost first, runs of the
+    // same call (as in deep recursion) are collapsed into a single note
+    pub fn with_trace(mut self, calls: impl IntoIterator<Item = (String, usize)>) -> Self {
+        let mut collapsed: Vec<(String, usize, usize)> = Vec::new();
+        for (function, line) in calls {
+            match collapsed.last_mut() {
+                Some((last, last_line, count)) if *last == function && *last_line == line => {
+                    *count += 1
+                }
+                _ => collapsed.push((function, line, 1)),
+            }
+        }
+        for (function, line, count) in collapsed.iter().take(MAX_TRACE_NOTES) {
+            self = self.with_note(format!("in {} at line {}", function, line));
+            if *count > 1 {
+                self = self.with_note(format!("... {} more frames in {}", count - 1, function));
+            }
+        }
+        if collapsed.len() > MAX_TRACE_NOTES {
+            let rest: usize = collapsed[MAX_TRACE_NOTES..].iter().map(|(_, _, count)| count).sum();
+            self = self.with_note(format!("... {} more frames", rest));
+        }
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    // prints the diagnostic to stderr, with colours if stderr is a terminal
+    pub fn emit(&self, source_name: &str, source: &str) {
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        eprint!("{}", self.render(source_name, source, color));
+    }
+
+    pub fn render(&self, source_name: &str, source: &str, color: bool) -> String {
+        let paint = |code: &'static str| if color { code } else { "" };
+        let reset = paint(RESET);
+        let (severity, severity_color) = match self.severity {
+            Severity::Error => ("error", paint(RED)),
+            Severity::Warning => ("warning", paint(YELLOW)),
+        };
+        let gutter_color = paint(BLUE);
+
+        // the primary span first, then every label sorted by line
+        let mut marks = vec![(self.span, self.primary_label.as_deref().unwrap_or(""), true)];
+        marks.extend(self.labels.iter().map(|label| (label.span, label.message.as_str(), false)));
+        marks.sort_by_key(|(span, _, primary)| (span.start.line, span.start.col, !primary));
+
+        let last_line = marks.iter().map(|(span, _, _)| span.start.line).max().unwrap_or(1);
+        let width = last_line.to_string().len();
+        let gutter = " ".repeat(width);
+
+        let mut out = format!(
+            "{severity_color}{severity}{reset}{bold}: {}{reset}\n",
+            self.message,
+            bold = paint(BOLD),
+        );
+        out += &format!(
+            "{gutter}{gutter_color}-->{reset} {}:{}:{}\n",
+            source_name, self.span.start.line, self.span.start.col
+        );
+        out += &format!("{gutter} {gutter_color}|{reset}\n");
+
+        let mut previous_line = None;
+        for (span, message, primary) in marks {
+            let line = span.start.line;
+            let text = source.lines().nth(line - 1).unwrap_or("");
+            if previous_line != Some(line) {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    out += &format!("{gutter_color}...{reset}\n");
+                }
+                out += &format!("{gutter_color}{line:>width$} |{reset} {text}\n");
+                previous_line = Some(line);
+            }
+
+            // spans that run over several lines are underlined up to the end of their first line
+            let line_width = display_width(text);
+            let end_col = if span.end.line == line && span.end.col > span.start.col {
+                span.end.col
+            } else {
+                (line_width + 1).max(span.start.col + 1)
+            };
+            // keep tabs so the carets line up with the source above them
+            let mut indent = String::new();
+            let mut width = 0;
+            for grapheme in text.graphemes(true) {
+                if width + 1 >= span.start.col {
+                    break;
+                }
+                if grapheme == "\t" {
+                    indent.push('\t');
+                } else {
+                    indent += &" ".repeat(grapheme.width());
+                }
+                width += grapheme.width();
+            }
+            indent += &" ".repeat((span.start.col - 1).saturating_sub(width));
+            let (marker, marker_color) = if primary {
+                ('^', severity_color)
+            } else {
+                ('-', gutter_color)
+            };
+            let underline = marker.to_string().repeat(end_col - span.start.col);
+            let message = if message.is_empty() {
+                String::new()
+            } else {
+                format!(" {message}")
+            };
+            out += &format!(
+                "{gutter} {gutter_color}|{reset} {indent}{marker_color}{underline}{message}{reset}\n"
+            );
+        }
+
+        for note in &self.notes {
+            out += &format!("{gutter} {gutter_color}={reset} {bold}note{reset}: {note}\n", bold = paint(BOLD));
+        }
+        if let Some(help) = &self.help {
+            out += &format!("{gutter} {gutter_color}={reset} {bold}help{reset}: {help}\n", bold = paint(BOLD));
+        }
+        out
+    }
+}
+
+// How many columns the text takes up on screen, counted by grapheme so that
+// combining marks add nothing and wide glyphs and emoji add two
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(|grapheme| grapheme.width()).sum()
+}
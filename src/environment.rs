@@ -0,0 +1,55 @@
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// One scope of variable bindings, linked to the scope it is nested in
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::new()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub fn define(&mut self, name: String, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    // returns false if the variable isn't defined in any enclosing scope
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::diagnostic::Span;
 use crate::scanner::Location;
 
 #[derive(Debug)]
@@ -6,22 +7,60 @@ pub enum Expr {
     Bool(bool),
     Number(f64),
     String(String),
-    Negate(Box<Expr>),
-    Add(Box<Expr>, Box<Expr>),
-    Sub(Box<Expr>, Box<Expr>),
-    Mul(Box<Expr>, Box<Expr>),
-    Div(Box<Expr>, Box<Expr>),
-    Mod(Box<Expr>, Box<Expr>),
-    Eq(Box<Expr>, Box<Expr>),
-    Neq(Box<Expr>, Box<Expr>),
-    Greater(Box<Expr>, Box<Expr>),
-    Less(Box<Expr>, Box<Expr>),
-    GreaterEqual(Box<Expr>, Box<Expr>),
-    LessEqual(Box<Expr>, Box<Expr>),
+    // the parts are stringified like `print` does and concatenated
+    Interpolation(Vec<LocExpr>),
+    Variable(String),
+    Assign(String, Box<LocExpr>),
+    Negate(Box<LocExpr>),
+    Not(Box<LocExpr>),
+    And(Box<LocExpr>, Box<LocExpr>),
+    Or(Box<LocExpr>, Box<LocExpr>),
+    Add(Box<LocExpr>, Box<LocExpr>),
+    Sub(Box<LocExpr>, Box<LocExpr>),
+    Mul(Box<LocExpr>, Box<LocExpr>),
+    Div(Box<LocExpr>, Box<LocExpr>),
+    Mod(Box<LocExpr>, Box<LocExpr>),
+    Eq(Box<LocExpr>, Box<LocExpr>),
+    Neq(Box<LocExpr>, Box<LocExpr>),
+    Greater(Box<LocExpr>, Box<LocExpr>),
+    Less(Box<LocExpr>, Box<LocExpr>),
+    GreaterEqual(Box<LocExpr>, Box<LocExpr>),
+    LessEqual(Box<LocExpr>, Box<LocExpr>),
+    Call(Box<LocExpr>, Vec<LocExpr>),
+    Get(Box<LocExpr>, String),
+    Set(Box<LocExpr>, String, Box<LocExpr>),
+    List(Vec<LocExpr>),
+    // list[index]
+    Index(Box<LocExpr>, Box<LocExpr>),
+    SetIndex(Box<LocExpr>, Box<LocExpr>, Box<LocExpr>),
+    // `target op= value`, the target is a Variable, Get or Index and its parts are evaluated once
+    Compound(Box<LocExpr>, CompoundOp, Box<LocExpr>),
+    This,
+    Super(String),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CompoundOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug)]
 pub struct LocExpr {
-    expr: Expr,
-    start: Location,
-    end: Location,
-}
\ No newline at end of file
+    pub expr: Expr,
+    pub start: Location,
+    pub end: Location,
+}
+
+impl LocExpr {
+    pub fn new(expr: Expr, start: Location, end: Location) -> Self {
+        LocExpr { expr, start, end }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.start, self.end)
+    }
+}
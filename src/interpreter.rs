@@ -1,62 +1,646 @@
-use crate::expr::Expr;
-use crate::value::Value;
+use crate::environment::Environment;
+use crate::expr::{CompoundOp, Expr, LocExpr};
+use crate::object::{natives, Callable, Class, Instance, LoxFunction};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::stmt::{ClassDecl, LocStmt, Stmt};
+use crate::value::{Value, list_index};
+use std::cell::RefCell;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use thiserror::Error;
 
-pub struct Interpreter {}
+// Every error points at the subexpression that caused it
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error("Undefined variable '{name}'")]
+    UndefinedVariable { name: String, span: Span },
+    #[error("{message}")]
+    TypeMismatch { message: &'static str, span: Span },
+    #[error("Can only call functions and classes")]
+    NotCallable { span: Span },
+    #[error("Expected {expected} arguments but got {got}")]
+    ArityMismatch { expected: usize, got: usize, span: Span },
+    #[error("Only instances have {what}")]
+    NotAnInstance { what: &'static str, span: Span },
+    #[error("Undefined property '{name}'")]
+    UndefinedProperty { name: String, span: Span },
+    #[error("Superclass must be a class")]
+    SuperclassNotAClass { span: Span },
+    #[error("Only lists can be indexed")]
+    NotAList { span: Span },
+    #[error("{message}")]
+    InvalidIndex { message: String, span: Span },
+    #[error("Stack overflow")]
+    StackOverflow { span: Span },
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match self {
+            RuntimeError::UndefinedVariable { span, .. }
+            | RuntimeError::TypeMismatch { span, .. }
+            | RuntimeError::NotCallable { span }
+            | RuntimeError::ArityMismatch { span, .. }
+            | RuntimeError::NotAnInstance { span, .. }
+            | RuntimeError::UndefinedProperty { span, .. }
+            | RuntimeError::SuperclassNotAClass { span }
+            | RuntimeError::NotAList { span }
+            | RuntimeError::InvalidIndex { span, .. }
+            | RuntimeError::StackOverflow { span } => *span,
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        Diagnostic::error(error.to_string(), error.span())
+    }
+}
+
+// Break carries the value of a `return` up to the enclosing call
+type Flow = ControlFlow<Value>;
+
+// as deep as the vm lets calls go, its script frame included
+const MAX_CALL_DEPTH: usize = 1024;
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    // the number of Lox calls in progress
+    depth: usize,
+    // the functions the last runtime error unwound through and the line each was called from
+    unwound: Vec<(String, usize)>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
 
 impl Interpreter {
-    fn runtime_error(&self, msg: &str) -> ! {
-        eprintln!("[interpreter] {}", msg);
-        std::process::exit(1);
+    pub fn new() -> Self {
+        let mut globals = Environment::new();
+        for native in natives() {
+            globals.define(native.name.to_string(), Value::Callable(Callable::Native(native)));
+        }
+        let globals = Rc::new(RefCell::new(globals));
+        Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            depth: 0,
+            unwound: Vec::new(),
+        }
+    }
+
+    // Runs the program and returns the value of its trailing expression statement, if any
+    pub fn interpret(&mut self, program: &[LocStmt]) -> Result<Value, RuntimeError> {
+        let mut value = Value::Nil;
+        self.unwound.clear();
+        for (index, stmt) in program.iter().enumerate() {
+            let result = match &stmt.stmt {
+                Stmt::Expression(expr) if index + 1 == program.len() => {
+                    self.expression(expr).map(|result| {
+                        value = result;
+                        Flow::Continue(())
+                    })
+                }
+                _ => self.statement(stmt),
+            };
+            // the parser rejects `return` outside of functions, but stop anyway if one slips through
+            match result {
+                Ok(Flow::Break(_)) => break,
+                Ok(Flow::Continue(())) => {}
+                Err(err) => {
+                    // leave the interpreter usable for the next program, as the REPL does
+                    self.environment = Rc::clone(&self.globals);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    // the calls that led to the error, innermost first, with the line each one had got to
+    pub fn trace(&self, error: &RuntimeError) -> Vec<(String, usize)> {
+        let mut line = error.span().start.line;
+        let mut trace = Vec::new();
+        for (function, call_line) in &self.unwound {
+            trace.push((function.clone(), line));
+            line = *call_line;
+        }
+        trace.push(("<script>".to_string(), line));
+        trace
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
     }
-    pub fn interpret(&mut self, tree: Expr) {
-        let res = self.expression(tree);
-        println!("{}", res);
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_string(), value);
+    }
+
+    fn statement(&mut self, stmt: &LocStmt) -> Result<Flow, RuntimeError> {
+        match &stmt.stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+            }
+            Stmt::Print(expr) => {
+                let value = self.expression(expr)?;
+                println!("{}", value);
+            }
+            Stmt::Var(name, initializer, _) => {
+                let value = match initializer {
+                    Some(expr) => self.expression(expr)?,
+                    None => Value::Nil,
+                };
+                self.open_declaration_scope();
+                self.environment.borrow_mut().define(name.clone(), value);
+            }
+            Stmt::Block(statements) => {
+                let scope = Environment::with_enclosing(Rc::clone(&self.environment));
+                return self.block(statements, scope);
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                if self.expression(condition)?.is_truthy() {
+                    return self.statement(then_branch);
+                } else if let Some(else_branch) = else_branch {
+                    return self.statement(else_branch);
+                }
+            }
+            Stmt::While(condition, body) => {
+                while self.expression(condition)?.is_truthy() {
+                    if let Flow::Break(value) = self.statement(body)? {
+                        return Ok(Flow::Break(value));
+                    }
+                }
+            }
+            Stmt::Function(declaration) => {
+                self.open_declaration_scope();
+                let function = LoxFunction {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&self.environment),
+                    is_initializer: false,
+                };
+                let value = Value::Callable(Callable::LoxFunction(Rc::new(function)));
+                self.environment
+                    .borrow_mut()
+                    .define(declaration.name.clone(), value);
+            }
+            Stmt::Class(declaration) => self.class(declaration)?,
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.expression(expr)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Break(value));
+            }
+        }
+        Ok(Flow::Continue(()))
+    }
+
+    fn class(&mut self, declaration: &ClassDecl) -> Result<(), RuntimeError> {
+        let superclass = match &declaration.superclass {
+            Some(expr) => match self.expression(expr)? {
+                Value::Callable(Callable::Class(class)) => Some(class),
+                _ => return Err(RuntimeError::SuperclassNotAClass { span: expr.span() }),
+            },
+            None => None,
+        };
+
+        self.open_declaration_scope();
+        self.environment
+            .borrow_mut()
+            .define(declaration.name.clone(), Value::Nil);
+
+        // methods of a subclass see `super` in a scope of its own
+        let method_scope = match &superclass {
+            Some(superclass) => {
+                let mut scope = Environment::with_enclosing(Rc::clone(&self.environment));
+                scope.define("super".to_string(), Value::Callable(Callable::Class(Rc::clone(superclass))));
+                Rc::new(RefCell::new(scope))
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let class = Class::new(declaration.name.clone());
+        if let Some(superclass) = &superclass {
+            class
+                .methods
+                .borrow_mut()
+                .extend(superclass.methods.borrow().clone());
+        }
+        for method in &declaration.methods {
+            let function = LoxFunction {
+                declaration: Rc::clone(method),
+                closure: Rc::clone(&method_scope),
+                is_initializer: method.name == "init",
+            };
+            class
+                .methods
+                .borrow_mut()
+                .insert(method.name.clone(), Callable::LoxFunction(Rc::new(function)));
+        }
+
+        let class = Value::Callable(Callable::Class(Rc::new(class)));
+        self.environment.borrow_mut().assign(&declaration.name, class);
+        Ok(())
+    }
+
+    // Every local declaration gets a scope of its own, so closures created before it can't see it.
+    // Globals are late bound and stay in one shared scope.
+    fn open_declaration_scope(&mut self) {
+        if !Rc::ptr_eq(&self.environment, &self.globals) {
+            let scope = Environment::with_enclosing(Rc::clone(&self.environment));
+            self.environment = Rc::new(RefCell::new(scope));
+        }
     }
 
-    fn expression(&mut self, tree: Expr) -> Value {
-        match tree {
+    fn block(&mut self, statements: &[LocStmt], scope: Environment) -> Result<Flow, RuntimeError> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(scope)));
+        let mut result = Ok(Flow::Continue(()));
+        for stmt in statements {
+            result = self.statement(stmt);
+            if !matches!(result, Ok(Flow::Continue(()))) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    fn expression(&mut self, tree: &LocExpr) -> Result<Value, RuntimeError> {
+        let value = match &tree.expr {
             Expr::Null => Value::Nil,
-            Expr::Bool(b) => Value::Bool(b),
-            Expr::Number(f) => Value::Number(f),
-            Expr::String(s) => Value::String(s),
-            Expr::Negate(e) => self.unary_negate(*e),
-            Expr::Add(a, b) => self.numeric_op(*a, *b, |a, b| Value::Number(a + b)),
-            Expr::Sub(a, b) => self.numeric_op(*a, *b, |a, b| Value::Number(a - b)),
-            Expr::Mul(a, b) => self.numeric_op(*a, *b, |a, b| Value::Number(a * b)),
-            Expr::Div(a, b) => self.numeric_op(*a, *b, |a, b| Value::Number(a / b)),
-            Expr::Mod(a, b) => self.numeric_op(*a, *b, |a, b| Value::Number(a % b)),
-            Expr::Eq(a, b) => self.comparison(*a, *b, |a, b| a == b),
-            Expr::Neq(a, b) => self.comparison(*a, *b, |a, b| a != b),
-            Expr::Greater(a, b) => self.numeric_op(*a, *b, |a, b| Value::Bool(a > b)),
-            Expr::Less(a, b) => self.numeric_op(*a, *b, |a, b| Value::Bool(a < b)),
-            Expr::GreaterEqual(a, b) => self.numeric_op(*a, *b, |a, b| Value::Bool(a >= b)),
-            Expr::LessEqual(a, b) => self.numeric_op(*a, *b, |a, b| Value::Bool(a <= b)),
-        }
-    }
-
-    fn unary_negate(&mut self, expr: Expr) -> Value {
-        if let Value::Number(n) = self.expression(expr) {
-            Value::Number(-n)
-        } else {
-            self.runtime_error("Type mismatch: unary '-' applied to non-number");
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Number(f) => Value::Number(*f),
+            Expr::String(s) => Value::String(s.clone()),
+            Expr::Interpolation(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    text += &self.expression(part)?.to_string();
+                }
+                Value::String(text)
+            }
+            Expr::Variable(name) => self.variable(name, tree.span())?,
+            Expr::Assign(name, value) => self.assign(name, value, tree.span())?,
+            Expr::Negate(e) => self.unary_negate(e)?,
+            Expr::Not(e) => Value::Bool(!self.expression(e)?.is_truthy()),
+            Expr::And(a, b) => {
+                let left = self.expression(a)?;
+                if !left.is_truthy() {
+                    left
+                } else {
+                    self.expression(b)?
+                }
+            }
+            Expr::Or(a, b) => {
+                let left = self.expression(a)?;
+                if left.is_truthy() {
+                    left
+                } else {
+                    self.expression(b)?
+                }
+            }
+            Expr::Add(a, b) => self.add(a, b)?,
+            Expr::Sub(a, b) => self.numeric_op(a, b, |a, b| Value::Number(a - b))?,
+            Expr::Mul(a, b) => self.numeric_op(a, b, |a, b| Value::Number(a * b))?,
+            Expr::Div(a, b) => self.numeric_op(a, b, |a, b| Value::Number(a / b))?,
+            Expr::Mod(a, b) => self.numeric_op(a, b, |a, b| Value::Number(a % b))?,
+            Expr::Eq(a, b) => self.comparison(a, b, |a, b| a == b)?,
+            Expr::Neq(a, b) => self.comparison(a, b, |a, b| a != b)?,
+            Expr::Greater(a, b) => self.numeric_op(a, b, |a, b| Value::Bool(a > b))?,
+            Expr::Less(a, b) => self.numeric_op(a, b, |a, b| Value::Bool(a < b))?,
+            Expr::GreaterEqual(a, b) => self.numeric_op(a, b, |a, b| Value::Bool(a >= b))?,
+            Expr::LessEqual(a, b) => self.numeric_op(a, b, |a, b| Value::Bool(a <= b))?,
+            Expr::Call(callee, args) => self.call(callee, args, tree.span())?,
+            Expr::Get(object, name) => match self.expression(object)? {
+                Value::Instance(instance) => self.get_property(instance, name, tree.span())?,
+                _ => {
+                    return Err(RuntimeError::NotAnInstance {
+                        what: "properties",
+                        span: object.span(),
+                    })
+                }
+            },
+            Expr::Set(object, name, value) => {
+                let Value::Instance(instance) = self.expression(object)? else {
+                    return Err(RuntimeError::NotAnInstance {
+                        what: "fields",
+                        span: object.span(),
+                    });
+                };
+                let value = self.expression(value)?;
+                instance
+                    .borrow_mut()
+                    .fields
+                    .insert(name.clone(), value.clone());
+                value
+            }
+            Expr::List(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.expression(element)?);
+                }
+                Value::from(values)
+            }
+            Expr::Index(list, index) => {
+                let list_value = self.expression(list)?;
+                let index_value = self.expression(index)?;
+                self.get_index(list_value, index_value, index)?
+            }
+            Expr::SetIndex(list, index, value) => {
+                let list_value = self.expression(list)?;
+                let index_value = self.expression(index)?;
+                let value = self.expression(value)?;
+                self.set_index(list_value, index_value, index, value)?
+            }
+            Expr::Compound(target, op, value) => self.compound(target, *op, value)?,
+            Expr::This => self.variable("this", tree.span())?,
+            Expr::Super(name) => {
+                let Value::Callable(Callable::Class(superclass)) = self.variable("super", tree.span())? else {
+                    return Err(RuntimeError::SuperclassNotAClass { span: tree.span() });
+                };
+                let this = self.variable("this", tree.span())?;
+                match superclass.find_method(name) {
+                    Some(Callable::LoxFunction(method)) => bind(&method, this),
+                    _ => {
+                        return Err(RuntimeError::UndefinedProperty {
+                            name: name.clone(),
+                            span: tree.span(),
+                        })
+                    }
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    fn get_index(
+        &self,
+        list: Value,
+        index: Value,
+        index_expr: &LocExpr,
+    ) -> Result<Value, RuntimeError> {
+        let Value::List(list) = list else {
+            return Err(RuntimeError::NotAList { span: index_expr.span() });
+        };
+        let list = list.borrow();
+        let index = list_index(&index, list.len()).map_err(|message| RuntimeError::InvalidIndex {
+            message,
+            span: index_expr.span(),
+        })?;
+        Ok(list[index].clone())
+    }
+
+    fn set_index(
+        &self,
+        list: Value,
+        index: Value,
+        index_expr: &LocExpr,
+        value: Value,
+    ) -> Result<Value, RuntimeError> {
+        let Value::List(list) = list else {
+            return Err(RuntimeError::NotAList { span: index_expr.span() });
+        };
+        let mut list = list.borrow_mut();
+        let index = list_index(&index, list.len()).map_err(|message| RuntimeError::InvalidIndex {
+            message,
+            span: index_expr.span(),
+        })?;
+        list[index] = value.clone();
+        Ok(value)
+    }
+
+    // reads the target, combines it with the value and stores the result back into the target
+    fn compound(
+        &mut self,
+        target: &LocExpr,
+        op: CompoundOp,
+        value: &LocExpr,
+    ) -> Result<Value, RuntimeError> {
+        match &target.expr {
+            Expr::Variable(name) => {
+                let old = self.variable(name, target.span())?;
+                let new = self.apply(op, old, value)?;
+                self.environment.borrow_mut().assign(name, new.clone());
+                Ok(new)
+            }
+            Expr::Get(object, name) => {
+                let Value::Instance(instance) = self.expression(object)? else {
+                    return Err(RuntimeError::NotAnInstance {
+                        what: "properties",
+                        span: object.span(),
+                    });
+                };
+                let old = self.get_property(Rc::clone(&instance), name, target.span())?;
+                let new = self.apply(op, old, value)?;
+                instance.borrow_mut().fields.insert(name.clone(), new.clone());
+                Ok(new)
+            }
+            Expr::Index(list, index) => {
+                let list_value = self.expression(list)?;
+                let index_value = self.expression(index)?;
+                let old = self.get_index(list_value.clone(), index_value.clone(), index)?;
+                let new = self.apply(op, old, value)?;
+                self.set_index(list_value, index_value, index, new)
+            }
+            _ => unreachable!("The parser only allows variables, fields and indexes as targets"),
+        }
+    }
+
+    fn apply(
+        &mut self,
+        op: CompoundOp,
+        old: Value,
+        value: &LocExpr,
+    ) -> Result<Value, RuntimeError> {
+        let rhs = self.expression(value)?;
+        match op {
+            CompoundOp::Add => add_values(old, rhs, value),
+            CompoundOp::Sub => numeric_values(old, rhs, value, |a, b| Value::Number(a - b)),
+            CompoundOp::Mul => numeric_values(old, rhs, value, |a, b| Value::Number(a * b)),
+            CompoundOp::Div => numeric_values(old, rhs, value, |a, b| Value::Number(a / b)),
+            CompoundOp::Mod => numeric_values(old, rhs, value, |a, b| Value::Number(a % b)),
+        }
+    }
+
+    fn get_property(
+        &mut self,
+        instance: Rc<RefCell<Instance>>,
+        name: &str,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(name) {
+            return Ok(value.clone());
+        }
+        let method = instance.borrow().class.find_method(name);
+        match method {
+            Some(Callable::LoxFunction(method)) => Ok(bind(&method, Value::Instance(instance))),
+            _ => Err(RuntimeError::UndefinedProperty {
+                name: name.to_string(),
+                span,
+            }),
         }
     }
 
-    fn numeric_op(&mut self, left: Expr, right: Expr, func: fn(f64, f64) -> Value) -> Value {
-        let a = self.expression(left);
-        let b = self.expression(right);
+    fn call(&mut self, callee: &LocExpr, args: &[LocExpr], span: Span) -> Result<Value, RuntimeError> {
+        let Value::Callable(callee) = self.expression(callee)? else {
+            return Err(RuntimeError::NotCallable { span: callee.span() });
+        };
+        let mut arguments = Vec::with_capacity(args.len());
+        for arg in args {
+            arguments.push(self.expression(arg)?);
+        }
+        if arguments.len() != callee.arity() {
+            return Err(RuntimeError::ArityMismatch {
+                expected: callee.arity(),
+                got: arguments.len(),
+                span,
+            });
+        }
 
-        if let (Value::Number(a), Value::Number(b)) = (a, b) {
-            return
-                func(a, b);
+        match callee {
+            Callable::LoxFunction(function) => self.call_function(&function, arguments, span),
+            Callable::Native(native) => Ok((native.function)(&arguments)),
+            Callable::Class(class) => {
+                let instance = Value::Instance(Rc::new(RefCell::new(Instance::new(Rc::clone(&class)))));
+                if let Some(Callable::LoxFunction(init)) = class.find_method("init") {
+                    let Value::Callable(Callable::LoxFunction(init)) = bind(&init, instance.clone()) else {
+                        unreachable!("Binding a method always yields a function")
+                    };
+                    self.call_function(&init, arguments, span)?;
+                }
+                Ok(instance)
+            }
+            Callable::Closure(_) | Callable::BoundMethod(_) => Err(RuntimeError::NotCallable { span }),
         }
-        self.runtime_error("Type mismatch in numeric operation at ??? - Error locations not yet implemented");
     }
 
-    fn comparison(&mut self, left: Expr, right: Expr, func: fn(Value, Value) -> bool) -> Value {
-        let a = self.expression(left);
-        let b = self.expression(right);
+    fn call_function(
+        &mut self,
+        function: &LoxFunction,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        if self.depth + 1 == MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow { span });
+        }
+        let mut scope = Environment::with_enclosing(Rc::clone(&function.closure));
+        for (param, arg) in function.declaration.params.iter().zip(arguments) {
+            scope.define(param.clone(), arg);
+        }
+        // a Lox call takes many nested Rust calls, so the stack grows onto the heap when it runs low
+        self.depth += 1;
+        let result = stacker::maybe_grow(128 * 1024, 2 * 1024 * 1024, || {
+            self.block(&function.declaration.body, scope)
+        });
+        self.depth -= 1;
+        let result = match result {
+            Ok(Flow::Break(value)) => value,
+            Ok(Flow::Continue(())) => Value::Nil,
+            Err(err) => {
+                let function = format!("<fn {}>", function.declaration.name);
+                self.unwound.push((function, span.start.line));
+                return Err(err);
+            }
+        };
+        if function.is_initializer {
+            // initializers always hand back the instance, even on a bare `return;`
+            return Ok(function.closure.borrow().get("this").unwrap_or(Value::Nil));
+        }
+        Ok(result)
+    }
 
-        Value::Bool(func(a, b))
+    fn variable(&self, name: &str, span: Span) -> Result<Value, RuntimeError> {
+        match self.environment.borrow().get(name) {
+            Some(value) => Ok(value),
+            None => Err(RuntimeError::UndefinedVariable {
+                name: name.to_string(),
+                span,
+            }),
+        }
     }
-}
\ No newline at end of file
+
+    fn assign(&mut self, name: &str, expr: &LocExpr, span: Span) -> Result<Value, RuntimeError> {
+        let value = self.expression(expr)?;
+        if self.environment.borrow_mut().assign(name, value.clone()) {
+            Ok(value)
+        } else {
+            Err(RuntimeError::UndefinedVariable {
+                name: name.to_string(),
+                span,
+            })
+        }
+    }
+
+    fn unary_negate(&mut self, expr: &LocExpr) -> Result<Value, RuntimeError> {
+        if let Value::Number(n) = self.expression(expr)? {
+            Ok(Value::Number(-n))
+        } else {
+            Err(RuntimeError::TypeMismatch {
+                message: "Operand must be a number",
+                span: expr.span(),
+            })
+        }
+    }
+
+    fn add(&mut self, left: &LocExpr, right: &LocExpr) -> Result<Value, RuntimeError> {
+        let a = self.expression(left)?;
+        let b = self.expression(right)?;
+        add_values(a, b, right)
+    }
+
+    fn numeric_op(&mut self, left: &LocExpr, right: &LocExpr, func: fn(f64, f64) -> Value) -> Result<Value, RuntimeError> {
+        let a = self.expression(left)?;
+        let b = self.expression(right)?;
+        numeric_values(a, b, right, func)
+    }
+
+    fn comparison(&mut self, left: &LocExpr, right: &LocExpr, func: fn(Value, Value) -> bool) -> Result<Value, RuntimeError> {
+        let a = self.expression(left)?;
+        let b = self.expression(right)?;
+
+        Ok(Value::Bool(func(a, b)))
+    }
+}
+
+// type errors point at the right operand, the vm only knows where that one starts
+fn add_values(a: Value, b: Value, right: &LocExpr) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+        (Value::String(a), Value::String(b)) => Ok(Value::String(a + &b)),
+        _ => Err(RuntimeError::TypeMismatch {
+            message: "Operands must be two numbers or two strings",
+            span: right.span(),
+        }),
+    }
+}
+
+fn numeric_values(
+    a: Value,
+    b: Value,
+    right: &LocExpr,
+    func: fn(f64, f64) -> Value,
+) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(func(a, b)),
+        _ => Err(RuntimeError::TypeMismatch {
+            message: "Operands must be numbers",
+            span: right.span(),
+        }),
+    }
+}
+
+// creates a copy of the method whose scope has `this` bound to the given instance
+fn bind(method: &LoxFunction, instance: Value) -> Value {
+    let mut scope = Environment::with_enclosing(Rc::clone(&method.closure));
+    scope.define("this".to_string(), instance);
+    let bound = LoxFunction {
+        declaration: Rc::clone(&method.declaration),
+        closure: Rc::new(RefCell::new(scope)),
+        is_initializer: method.is_initializer,
+    };
+    Value::Callable(Callable::LoxFunction(Rc::new(bound)))
+}
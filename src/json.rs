@@ -0,0 +1,322 @@
+// The front end as JSON, for `rox tokens`, `rox ast` and tools built on top of them.
+// The names and fields below are a stable format, they don't follow renames in the Rust types.
+//
+// A location is `{"line": 1, "col": 1, "offset": 0}`, `col` is the column on screen and
+// `offset` the byte offset into the source. Every node has a "start" and "end" location.
+//
+// Tokens are `{"type", "lexeme", "start", "end"}`, where "type" is the variant name of
+// `TokenType` ("LeftParenthesis", "Identifier", "Var", ...) and "lexeme" the token as written.
+// Some types add a field:
+//   Identifier                              "name", in NFC
+//   StringLiteral, StringInterpolation      "value", with escapes resolved
+//   NumberLiteral                           "value"
+//   DocComment                              "text", without the markers
+//   Invalid                                 "message"
+//
+// Statements and expressions are `{"kind", "start", "end", ...}`:
+//   Expression, Print     "expression"
+//   Var                   "name", "initializer" (or null), "doc" (or null)
+//   Block                 "statements"
+//   If                    "condition", "then", "else" (or null)
+//   While                 "condition", "body"
+//   Function              "name", "params", "body", "doc" (or null)
+//   Return                "value" (or null)
+//   Class                 "name", "superclass" (or null), "methods", "doc" (or null)
+//   Nil, This
+//   Bool, Number, String  "value"
+//   Interpolation         "parts"
+//   Variable              "name"
+//   Assign                "name", "value"
+//   Unary                 "operator" ("-" or "!"), "operand"
+//   Logical               "operator" ("and" or "or"), "left", "right"
+//   Binary                "operator" ("+", "==", "<=", ...), "left", "right"
+//   Call                  "callee", "arguments"
+//   Get                   "object", "name"
+//   Set                   "object", "name", "value"
+//   List                  "elements"
+//   Index                 "list", "index"
+//   SetIndex              "list", "index", "value"
+//   Compound              "operator" ("+=", "-=", "*=", "/=" or "%="), "target", "value"
+//   Super                 "method"
+// Methods are Function nodes without "start" and "end".
+use crate::expr::{CompoundOp, Expr, LocExpr};
+use crate::scanner::{Location, Scanner};
+use crate::stmt::{FunctionDecl, LocStmt, Stmt};
+use crate::token::{Token, TokenType};
+use serde_json::{Map, Value, json};
+
+// every token of the source, invalid ones included
+pub fn tokens(source: &str) -> Value {
+    Scanner::new(source)
+        .map(|token| token_json(source, &token))
+        .collect()
+}
+
+pub fn ast(program: &[LocStmt]) -> Value {
+    program.iter().map(stmt_json).collect()
+}
+
+fn location_json(location: Location) -> Value {
+    json!({
+        "line": location.line,
+        "col": location.col,
+        "offset": location.index,
+    })
+}
+
+fn token_json(source: &str, token: &Token) -> Value {
+    let mut object = Map::new();
+    object.insert("type".into(), token_type_name(&token.token_type).into());
+    object.insert(
+        "lexeme".into(),
+        source[token.start.index..token.end.index].into(),
+    );
+    object.insert("start".into(), location_json(token.start));
+    object.insert("end".into(), location_json(token.end));
+    match &token.token_type {
+        TokenType::Identifier(name) => {
+            object.insert("name".into(), name.as_ref().into());
+        }
+        TokenType::StringLiteral(value) | TokenType::StringInterpolation(value) => {
+            object.insert("value".into(), value.as_ref().into());
+        }
+        TokenType::NumberLiteral(value) => {
+            object.insert("value".into(), (*value).into());
+        }
+        TokenType::DocComment(text) => {
+            object.insert("text".into(), (*text).into());
+        }
+        TokenType::Invalid(message) => {
+            object.insert("message".into(), message.as_str().into());
+        }
+        _ => {}
+    }
+    Value::Object(object)
+}
+
+fn token_type_name(token_type: &TokenType) -> &'static str {
+    match token_type {
+        TokenType::LeftBracket => "LeftBracket",
+        TokenType::RightBracket => "RightBracket",
+        TokenType::LeftBrace => "LeftBrace",
+        TokenType::RightBrace => "RightBrace",
+        TokenType::LeftParenthesis => "LeftParenthesis",
+        TokenType::RightParenthesis => "RightParenthesis",
+        TokenType::Comma => "Comma",
+        TokenType::Dot => "Dot",
+        TokenType::Minus => "Minus",
+        TokenType::Plus => "Plus",
+        TokenType::Semicolon => "Semicolon",
+        TokenType::Slash => "Slash",
+        TokenType::Star => "Star",
+        TokenType::PlusEqual => "PlusEqual",
+        TokenType::MinusEqual => "MinusEqual",
+        TokenType::StarEqual => "StarEqual",
+        TokenType::SlashEqual => "SlashEqual",
+        TokenType::ModuloEqual => "ModuloEqual",
+        TokenType::Bang => "Bang",
+        TokenType::BangEqual => "BangEqual",
+        TokenType::Equal => "Equal",
+        TokenType::EqualEqual => "EqualEqual",
+        TokenType::Greater => "Greater",
+        TokenType::GreaterEqual => "GreaterEqual",
+        TokenType::Less => "Less",
+        TokenType::LessEqual => "LessEqual",
+        TokenType::Identifier(_) => "Identifier",
+        TokenType::StringLiteral(_) => "StringLiteral",
+        TokenType::StringInterpolation(_) => "StringInterpolation",
+        TokenType::NumberLiteral(_) => "NumberLiteral",
+        TokenType::DocComment(_) => "DocComment",
+        TokenType::And => "And",
+        TokenType::Class => "Class",
+        TokenType::Else => "Else",
+        TokenType::False => "False",
+        TokenType::For => "For",
+        TokenType::Fun => "Fun",
+        TokenType::If => "If",
+        TokenType::Nil => "Nil",
+        TokenType::Or => "Or",
+        TokenType::Print => "Print",
+        TokenType::Return => "Return",
+        TokenType::Super => "Super",
+        TokenType::This => "This",
+        TokenType::True => "True",
+        TokenType::Var => "Var",
+        TokenType::While => "While",
+        TokenType::Modulo => "Modulo",
+        TokenType::Invalid(_) => "Invalid",
+    }
+}
+
+// a node of the given kind, the fields follow the location
+fn node(kind: &str, start: Location, end: Location, fields: Value) -> Value {
+    let mut object = Map::new();
+    object.insert("kind".into(), kind.into());
+    object.insert("start".into(), location_json(start));
+    object.insert("end".into(), location_json(end));
+    if let Value::Object(fields) = fields {
+        object.extend(fields);
+    }
+    Value::Object(object)
+}
+
+fn stmt_json(stmt: &LocStmt) -> Value {
+    let (kind, fields) = match &stmt.stmt {
+        Stmt::Expression(expr) => ("Expression", json!({ "expression": expr_json(expr) })),
+        Stmt::Print(expr) => ("Print", json!({ "expression": expr_json(expr) })),
+        Stmt::Var(name, initializer, doc) => (
+            "Var",
+            json!({
+                "name": name,
+                "initializer": initializer.as_ref().map(expr_json),
+                "doc": doc,
+            }),
+        ),
+        Stmt::Block(stmts) => ("Block", json!({ "statements": ast(stmts) })),
+        Stmt::If(condition, then_branch, else_branch) => (
+            "If",
+            json!({
+                "condition": expr_json(condition),
+                "then": stmt_json(then_branch),
+                "else": else_branch.as_deref().map(stmt_json),
+            }),
+        ),
+        Stmt::While(condition, body) => (
+            "While",
+            json!({
+                "condition": expr_json(condition),
+                "body": stmt_json(body),
+            }),
+        ),
+        Stmt::Function(function) => ("Function", function_fields(function)),
+        Stmt::Return(value) => ("Return", json!({ "value": value.as_ref().map(expr_json) })),
+        Stmt::Class(class) => {
+            let methods: Vec<Value> = class
+                .methods
+                .iter()
+                .map(|method| {
+                    let mut object = Map::new();
+                    object.insert("kind".into(), "Function".into());
+                    if let Value::Object(fields) = function_fields(method) {
+                        object.extend(fields);
+                    }
+                    Value::Object(object)
+                })
+                .collect();
+            (
+                "Class",
+                json!({
+                    "name": class.name,
+                    "superclass": class.superclass.as_ref().map(expr_json),
+                    "methods": methods,
+                    "doc": class.doc,
+                }),
+            )
+        }
+    };
+    node(kind, stmt.start, stmt.end, fields)
+}
+
+fn function_fields(function: &FunctionDecl) -> Value {
+    json!({
+        "name": function.name,
+        "params": function.params,
+        "body": ast(&function.body),
+        "doc": function.doc,
+    })
+}
+
+fn expr_json(expr: &LocExpr) -> Value {
+    let binary = |operator: &str, left: &LocExpr, right: &LocExpr| {
+        json!({
+            "operator": operator,
+            "left": expr_json(left),
+            "right": expr_json(right),
+        })
+    };
+    let (kind, fields) = match &expr.expr {
+        Expr::Null => ("Nil", json!({})),
+        Expr::Bool(value) => ("Bool", json!({ "value": value })),
+        Expr::Number(value) => ("Number", json!({ "value": value })),
+        Expr::String(value) => ("String", json!({ "value": value })),
+        Expr::Interpolation(parts) => {
+            let parts: Vec<Value> = parts.iter().map(expr_json).collect();
+            ("Interpolation", json!({ "parts": parts }))
+        }
+        Expr::Variable(name) => ("Variable", json!({ "name": name })),
+        Expr::Assign(name, value) => ("Assign", json!({ "name": name, "value": expr_json(value) })),
+        Expr::Negate(operand) => (
+            "Unary",
+            json!({ "operator": "-", "operand": expr_json(operand) }),
+        ),
+        Expr::Not(operand) => (
+            "Unary",
+            json!({ "operator": "!", "operand": expr_json(operand) }),
+        ),
+        Expr::And(left, right) => ("Logical", binary("and", left, right)),
+        Expr::Or(left, right) => ("Logical", binary("or", left, right)),
+        Expr::Add(left, right) => ("Binary", binary("+", left, right)),
+        Expr::Sub(left, right) => ("Binary", binary("-", left, right)),
+        Expr::Mul(left, right) => ("Binary", binary("*", left, right)),
+        Expr::Div(left, right) => ("Binary", binary("/", left, right)),
+        Expr::Mod(left, right) => ("Binary", binary("%", left, right)),
+        Expr::Eq(left, right) => ("Binary", binary("==", left, right)),
+        Expr::Neq(left, right) => ("Binary", binary("!=", left, right)),
+        Expr::Greater(left, right) => ("Binary", binary(">", left, right)),
+        Expr::Less(left, right) => ("Binary", binary("<", left, right)),
+        Expr::GreaterEqual(left, right) => ("Binary", binary(">=", left, right)),
+        Expr::LessEqual(left, right) => ("Binary", binary("<=", left, right)),
+        Expr::Call(callee, arguments) => {
+            let arguments: Vec<Value> = arguments.iter().map(expr_json).collect();
+            (
+                "Call",
+                json!({ "callee": expr_json(callee), "arguments": arguments }),
+            )
+        }
+        Expr::Get(object, name) => ("Get", json!({ "object": expr_json(object), "name": name })),
+        Expr::Set(object, name, value) => (
+            "Set",
+            json!({
+                "object": expr_json(object),
+                "name": name,
+                "value": expr_json(value),
+            }),
+        ),
+        Expr::List(elements) => {
+            let elements: Vec<Value> = elements.iter().map(expr_json).collect();
+            ("List", json!({ "elements": elements }))
+        }
+        Expr::Index(list, index) => (
+            "Index",
+            json!({ "list": expr_json(list), "index": expr_json(index) }),
+        ),
+        Expr::SetIndex(list, index, value) => (
+            "SetIndex",
+            json!({
+                "list": expr_json(list),
+                "index": expr_json(index),
+                "value": expr_json(value),
+            }),
+        ),
+        Expr::Compound(target, op, value) => {
+            let operator = match op {
+                CompoundOp::Add => "+=",
+                CompoundOp::Sub => "-=",
+                CompoundOp::Mul => "*=",
+                CompoundOp::Div => "/=",
+                CompoundOp::Mod => "%=",
+            };
+            (
+                "Compound",
+                json!({
+                    "operator": operator,
+                    "target": expr_json(target),
+                    "value": expr_json(value),
+                }),
+            )
+        }
+        Expr::This => ("This", json!({})),
+        Expr::Super(method) => ("Super", json!({ "method": method })),
+    };
+    node(kind, expr.start, expr.end, fields)
+}
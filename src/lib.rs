@@ -0,0 +1,227 @@
+// Rox as a library. `Engine` runs source code end to end, the modules below
+// expose every stage on its own: scanner -> compiler (parser) -> interpreter,
+// or scanner -> compiler -> codegen -> vm.
+use crate::codegen::CodeGen;
+use crate::compiler::Parser;
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::Interpreter;
+use crate::stmt::{LocStmt, Stmt};
+use crate::trace::TraceConfig;
+use crate::value::Value;
+use crate::vm::Vm;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub mod chunk;
+pub mod codegen;
+pub mod compiler;
+#[cfg(feature = "trace")]
+mod debug;
+pub mod diagnostic;
+pub mod environment;
+pub mod expr;
+pub mod interpreter;
+pub mod json;
+pub mod object;
+pub mod scanner;
+pub mod stmt;
+pub mod token;
+pub mod trace;
+pub mod value;
+pub mod vm;
+
+const EVAL_SOURCE_NAME: &str = "<eval>";
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Backend {
+    // walks the syntax tree
+    #[default]
+    Interpreter,
+    // compiles to bytecode first
+    Vm,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Couldn't read '{path}': {error}")]
+    Io {
+        path: String,
+        #[source]
+        error: std::io::Error,
+    },
+    #[error("{}", render_all(source_name, code, diagnostics))]
+    Compile {
+        source_name: String,
+        code: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    #[error("{}", render_all(source_name, code, std::slice::from_ref(diagnostic)))]
+    Runtime {
+        source_name: String,
+        code: String,
+        diagnostic: Box<Diagnostic>,
+    },
+}
+
+fn render_all(source_name: &str, code: &str, diagnostics: &[Diagnostic]) -> String {
+    let rendered: String = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(source_name, code, false))
+        .collect();
+    rendered.trim_end().to_string()
+}
+
+impl Error {
+    // prints the error to stderr, diagnostics get their source snippets and colours
+    pub fn emit(&self) {
+        match self {
+            Error::Io { .. } => eprintln!("{}", self),
+            Error::Compile {
+                source_name,
+                code,
+                diagnostics,
+            } => {
+                for diagnostic in diagnostics {
+                    diagnostic.emit(source_name, code);
+                }
+            }
+            Error::Runtime {
+                source_name,
+                code,
+                diagnostic,
+            } => diagnostic.emit(source_name, code),
+        }
+    }
+
+    // the sysexits code a command line tool should exit with
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 66,
+            Error::Compile { .. } => 65,
+            Error::Runtime { .. } => 70,
+        }
+    }
+}
+
+enum Runtime {
+    Interpreter(Interpreter),
+    Vm(Vm),
+}
+
+// Runs Rox code, globals are kept between calls so a host can feed in code bit by bit
+pub struct Engine {
+    runtime: Runtime,
+    trace: TraceConfig,
+    // doc comments of the global declarations, methods are found under `Class.method`
+    docs: HashMap<String, String>,
+}
+
+impl Engine {
+    pub fn new(backend: Backend) -> Self {
+        Engine::with_trace(backend, TraceConfig::default())
+    }
+
+    pub fn with_trace(backend: Backend, trace: TraceConfig) -> Self {
+        let runtime = match backend {
+            Backend::Interpreter => Runtime::Interpreter(Interpreter::new()),
+            Backend::Vm => Runtime::Vm(Vm::with_trace(trace)),
+        };
+        Engine {
+            runtime,
+            trace,
+            docs: HashMap::new(),
+        }
+    }
+
+    // Runs the code and returns the value of its last statement if that is an expression
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, Error> {
+        self.eval_source(EVAL_SOURCE_NAME, source)
+    }
+
+    // like eval, the name shows up in error messages
+    pub fn eval_source(&mut self, source_name: &str, source: &str) -> Result<Option<Value>, Error> {
+        let compile_error = |diagnostics| Error::Compile {
+            source_name: source_name.to_string(),
+            code: source.to_string(),
+            diagnostics,
+        };
+        let program = Parser::new(source).compile().map_err(compile_error)?;
+        if self.trace.dump_ast {
+            trace::dump_ast(&program);
+        }
+        self.collect_docs(&program);
+        let has_value = program
+            .last()
+            .is_some_and(|stmt| matches!(stmt.stmt, Stmt::Expression(_)));
+
+        let result = match &mut self.runtime {
+            Runtime::Interpreter(interpreter) => interpreter.interpret(&program).map_err(|err| {
+                Box::new(Diagnostic::from(&err).with_trace(interpreter.trace(&err)))
+            }),
+            Runtime::Vm(vm) => {
+                let function = CodeGen::generate(&program).map_err(compile_error)?;
+                vm.run_function(function)
+            }
+        };
+        match result {
+            Ok(value) => Ok(has_value.then_some(value)),
+            Err(diagnostic) => Err(Error::Runtime {
+                source_name: source_name.to_string(),
+                code: source.to_string(),
+                diagnostic,
+            }),
+        }
+    }
+
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<Option<Value>, Error> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|error| Error::Io {
+            path: path.display().to_string(),
+            error,
+        })?;
+        self.eval_source(&path.display().to_string(), &source)
+    }
+
+    fn collect_docs(&mut self, program: &[LocStmt]) {
+        for stmt in program {
+            match &stmt.stmt {
+                Stmt::Var(name, _, doc) => self.set_doc(name.clone(), doc),
+                Stmt::Function(function) => self.set_doc(function.name.clone(), &function.doc),
+                Stmt::Class(class) => {
+                    self.set_doc(class.name.clone(), &class.doc);
+                    for method in &class.methods {
+                        self.set_doc(format!("{}.{}", class.name, method.name), &method.doc);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // a redeclaration without a doc comment forgets the old one
+    fn set_doc(&mut self, name: String, doc: &Option<String>) {
+        match doc {
+            Some(doc) => self.docs.insert(name, doc.clone()),
+            None => self.docs.remove(&name),
+        };
+    }
+
+    // the doc comment of a global variable, function or class, or of a method as `Class.method`
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.docs.get(name).map(String::as_str)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        match &self.runtime {
+            Runtime::Interpreter(interpreter) => interpreter.get_global(name),
+            Runtime::Vm(vm) => vm.get_global(name),
+        }
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        match &mut self.runtime {
+            Runtime::Interpreter(interpreter) => interpreter.set_global(name, value),
+            Runtime::Vm(vm) => vm.set_global(name, value),
+        }
+    }
+}
@@ -1,32 +1,86 @@
-use crate::compiler::Parser;
-use crate::interpreter::Interpreter;
+use rox::compiler::Parser;
+use rox::trace::TraceConfig;
+use rox::{Backend, Engine, Error};
+use std::io::Write;
 
-mod chunk;
-mod compiler;
-mod debug;
-mod scanner;
-mod token;
-mod value;
-mod vm;
-mod object;
-mod expr;
-mod interpreter;
+mod repl;
+
+const USAGE: &str = "Usage: rox [--vm] [--trace-exec] [--dump-ast] [--dump-bytecode] [filename]
+       rox tokens <filename>
+       rox ast <filename>";
 
 fn main() {
-    let mut args = std::env::args();
-    args.next();
-    if let Some(filename) = args.next() {
-        if args.next().is_some() {
-            println!("Usage: rox <filename>");
-            return;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // the front end as JSON, see rox::json for the format, a script named like a
+    // command can still be run as ./tokens
+    if let Some(command) = args.first()
+        && (command == "tokens" || command == "ast")
+    {
+        let [_, filename] = args.as_slice() else {
+            println!("{}", USAGE);
+            std::process::exit(64);
+        };
+        if let Err(err) = dump(command, filename) {
+            err.emit();
+            std::process::exit(err.exit_code());
         }
-        let file_content = std::fs::read_to_string(filename).expect("Couldn't read file");
-        let mut parser = Parser::new(&file_content);
-        parser.compile();
-        let mut interpreter = Interpreter{};
-        interpreter.interpret(parser.tree);
-    } else {
-        println!("Rox v0.1");
-        // REPL
+        return;
+    }
+
+    let mut use_vm = false;
+    let mut trace = TraceConfig::default();
+    let mut filename = None;
+    for arg in args {
+        match arg.as_str() {
+            "--vm" => use_vm = true,
+            "--trace-exec" => trace.trace_exec = true,
+            "--dump-ast" => trace.dump_ast = true,
+            "--dump-bytecode" => trace.dump_bytecode = true,
+            _ if arg.starts_with("--") || filename.is_some() => {
+                println!("{}", USAGE);
+                std::process::exit(64);
+            }
+            _ => filename = Some(arg),
+        }
+    }
+    if trace.is_enabled() && !cfg!(feature = "trace") {
+        eprintln!("[rox] Tracing was compiled out, rebuild with the 'trace' feature to use it");
+    }
+    if (trace.trace_exec || trace.dump_bytecode) && !use_vm {
+        eprintln!("[rox] --trace-exec and --dump-bytecode only apply to the vm, add --vm");
     }
+
+    let backend = if use_vm { Backend::Vm } else { Backend::Interpreter };
+
+    match filename {
+        Some(filename) => {
+            if let Err(err) = Engine::with_trace(backend, trace).run_file(&filename) {
+                err.emit();
+                std::process::exit(err.exit_code());
+            }
+        }
+        None => repl::run(backend, trace),
+    }
+}
+
+fn dump(command: &str, filename: &str) -> Result<(), Error> {
+    let source = std::fs::read_to_string(filename).map_err(|error| Error::Io {
+        path: filename.to_string(),
+        error,
+    })?;
+    let json = if command == "tokens" {
+        rox::json::tokens(&source)
+    } else {
+        let program = Parser::new(&source)
+            .compile()
+            .map_err(|diagnostics| Error::Compile {
+                source_name: filename.to_string(),
+                code: source.clone(),
+                diagnostics,
+            })?;
+        rox::json::ast(&program)
+    };
+    // a closed pipe, as in `rox tokens file | head`, is not an error
+    let _ = writeln!(std::io::stdout().lock(), "{:#}", json);
+    Ok(())
 }
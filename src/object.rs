@@ -0,0 +1,186 @@
+use crate::chunk::Chunk;
+use crate::environment::Environment;
+use crate::stmt::FunctionDecl;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Callable {
+    LoxFunction(Rc<LoxFunction>),
+    Closure(Rc<Closure>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+// A function of the tree-walking interpreter, together with the scope it was declared in
+pub struct LoxFunction {
+    pub declaration: Rc<FunctionDecl>,
+    pub closure: Rc<RefCell<Environment>>,
+    pub is_initializer: bool,
+}
+
+// A function compiled to bytecode, it only becomes callable when wrapped in a Closure
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalue_count: usize,
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// A captured variable: it lives on the vm stack while open and moves into the upvalue once closed
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+// Methods are stored as the callable of the engine that created the class.
+// Inherited methods are copied down into the subclass when it is created.
+pub struct Class {
+    pub name: String,
+    pub methods: RefCell<HashMap<String, Callable>>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Value>,
+}
+
+// A vm method that remembers the instance it was accessed on
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+impl Callable {
+    pub fn arity(&self) -> usize {
+        match self {
+            Callable::LoxFunction(f) => f.declaration.params.len(),
+            Callable::Closure(c) => c.function.arity,
+            Callable::Native(n) => n.arity,
+            Callable::Class(c) => c.find_method("init").map_or(0, |init| init.arity()),
+            Callable::BoundMethod(b) => b.method.function.arity,
+        }
+    }
+}
+
+impl Class {
+    pub fn new(name: String) -> Self {
+        Class {
+            name,
+            methods: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Callable> {
+        self.methods.borrow().get(name).cloned()
+    }
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Instance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+}
+
+impl PartialEq for Callable {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Callable::LoxFunction(a), Callable::LoxFunction(b)) => Rc::ptr_eq(a, b),
+            (Callable::Closure(a), Callable::Closure(b)) => Rc::ptr_eq(a, b),
+            (Callable::Native(a), Callable::Native(b)) => Rc::ptr_eq(a, b),
+            (Callable::Class(a), Callable::Class(b)) => Rc::ptr_eq(a, b),
+            (Callable::BoundMethod(a), Callable::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Callable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Callable::LoxFunction(func) => write!(f, "<fn {}>", func.declaration.name),
+            Callable::Closure(closure) => write!(f, "{}", closure.function),
+            Callable::Native(native) => write!(f, "<native fn {}>", native.name),
+            Callable::Class(class) => write!(f, "{}", class.name),
+            Callable::BoundMethod(bound) => write!(f, "{}", bound.method.function),
+        }
+    }
+}
+
+impl Debug for Callable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let variant = match self {
+            Callable::LoxFunction(_) => "LoxFunction",
+            Callable::Closure(_) => "Closure",
+            Callable::Native(_) => "Native",
+            Callable::Class(_) => "Class",
+            Callable::BoundMethod(_) => "BoundMethod",
+        };
+        write!(f, "{}({})", variant, self)
+    }
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("upvalue_count", &self.upvalue_count)
+            .finish_non_exhaustive()
+    }
+}
+
+// only the names of the fields, instances often refer back to each other
+impl Debug for Instance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut fields: Vec<&String> = self.fields.keys().collect();
+        fields.sort();
+        f.debug_struct("Instance")
+            .field("class", &self.class.name)
+            .field("fields", &fields)
+            .finish()
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "<script>")
+        } else {
+            write!(f, "<fn {}>", self.name)
+        }
+    }
+}
+
+pub fn clock(_args: &[Value]) -> Value {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Value::Number(now.as_secs_f64())
+}
+
+pub fn natives() -> Vec<Rc<NativeFunction>> {
+    vec![Rc::new(NativeFunction {
+        name: "clock",
+        arity: 0,
+        function: clock,
+    })]
+}
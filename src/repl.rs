@@ -0,0 +1,120 @@
+use rox::scanner::Scanner;
+use rox::token::TokenType;
+use rox::trace::TraceConfig;
+use rox::{Backend, Engine, Error};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use std::path::PathBuf;
+
+const SOURCE_NAME: &str = "<repl>";
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+pub fn run(backend: Backend, trace: TraceConfig) {
+    // the engine keeps globals alive between inputs
+    let mut engine = Engine::with_trace(backend, trace);
+    let mut editor = DefaultEditor::new().expect("Couldn't set up the line editor");
+    let history = history_file();
+    if let Some(history) = &history {
+        // there is no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    println!("Rox v0.1");
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+                if is_incomplete(&input) {
+                    continue;
+                }
+                let source = std::mem::take(&mut input);
+                if source.trim().is_empty() {
+                    continue;
+                }
+                let _ = editor.add_history_entry(source.trim_end());
+                if let Some(name) = source.trim().strip_prefix(":help") {
+                    help(&engine, name.trim());
+                    continue;
+                }
+                // a trailing expression prints its value, so `1 + 2` shows `3`
+                match eval(&mut engine, &source) {
+                    Ok(Some(value)) => println!("{}", value),
+                    Ok(None) => {}
+                    Err(err) => err.emit(),
+                }
+            }
+            // ctrl-c throws away the current input, ctrl-d leaves
+            Err(ReadlineError::Interrupted) => input.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("[repl] {}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history
+        && let Err(err) = editor.save_history(history)
+    {
+        eprintln!("[repl] Couldn't save history: {}", err);
+    }
+}
+
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rox_history"))
+}
+
+// Input continues on the next line while a string, block comment, brace, bracket or parenthesis
+// is open, or while a doc comment waits for its declaration
+fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut depth = 0isize;
+    let mut after_doc = false;
+    for token in scanner.by_ref() {
+        after_doc = matches!(token.token_type, TokenType::DocComment(_));
+        match token.token_type {
+            TokenType::LeftBrace | TokenType::LeftParenthesis | TokenType::LeftBracket => {
+                depth += 1
+            }
+            TokenType::RightBrace | TokenType::RightParenthesis | TokenType::RightBracket => {
+                depth -= 1
+            }
+            _ => {}
+        }
+    }
+    scanner.unterminated || depth > 0 || after_doc
+}
+
+// `:help name` shows the doc comment of a global or of a method as `Class.method`
+fn help(engine: &Engine, name: &str) {
+    if name.is_empty() {
+        println!("Usage: :help <name>, or :help <Class>.<method> for a method");
+        return;
+    }
+    match engine.doc(name) {
+        Some(doc) => println!("{}", doc),
+        None => println!("No documentation for '{}'", name),
+    }
+}
+
+// Runs the input, a missing ';' after the last statement is forgiven
+fn eval(engine: &mut Engine, source: &str) -> Result<Option<rox::value::Value>, Error> {
+    match engine.eval_source(SOURCE_NAME, source) {
+        Err(err @ Error::Compile { .. }) => {
+            let completed = format!("{};\n", source.trim_end());
+            match engine.eval_source(SOURCE_NAME, &completed) {
+                Err(Error::Compile { .. }) => Err(err),
+                result => result,
+            }
+        }
+        result => result,
+    }
+}
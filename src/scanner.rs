@@ -1,229 +1,662 @@
-use crate::token::{Token, TokenType};
+use crate::diagnostic::display_width;
+use crate::token::{Token, TokenTrivia, TokenType, Trivia, TriviaKind};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use unicode_ident::{is_xid_continue, is_xid_start};
+use unicode_normalization::{IsNormalized, UnicodeNormalization, is_nfc_quick};
+use unicode_segmentation::UnicodeSegmentation;
 
+// Hands out tokens one at a time as an iterator, scanning only as far as it has to
 #[derive(Debug)]
 pub struct Scanner<'a> {
-    source: std::iter::Peekable<std::str::Chars<'a>>,
-    pub tokens: Vec<Token>,
+    source: &'a str,
+    // tokens that are scanned but not handed out yet
+    queue: VecDeque<Token<'a>>,
+    finished: bool,
     start: Location,
     cur: Location,
+    // the last run of non-ascii characters, see advance
+    run_start: Location,
+    run_end: usize,
+    // set when the input ended inside a string or block comment
+    pub unterminated: bool,
+    // how many '{' are open inside each unfinished `${...}`, the innermost one is last
+    interpolations: Vec<usize>,
+    // see with_trivia
+    keep_trivia: bool,
+    // trivia that isn't attached to a token yet
+    pending_trivia: Vec<Trivia<'a>>,
+    // trivia after the last token, in trivia mode
+    pub end_trivia: Vec<Trivia<'a>>,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct Location {
     pub line: usize,
+    // the column on screen, wide glyphs take two and combining marks none
     pub col: usize,
+    // byte offset into the source
     pub index: usize,
 }
 
+impl Location {
+    pub const START: Location = Location {
+        line: 1,
+        col: 1,
+        index: 0,
+    };
+}
+
 impl<'a> Scanner<'a> {
-    pub(crate) fn new(source: &'a str) -> Self {
+    pub fn new(source: &'a str) -> Self {
         Scanner {
-            source: source.chars().peekable(),
-            tokens: Vec::new(),
-            start: Location {
-                line: 1,
-                col: 1,
-                index: 0,
-            },
-            cur: Location {
-                line: 1,
-                col: 1,
-                index: 0,
-            },
-        }
-    }
-
-    fn next(&mut self) -> Option<char> {
-        let c = self.source.next();
-        match c {
-            Some('\n') => {
-                self.cur.line += 1;
-                self.cur.col = 1;
-                self.cur.index += 1;
+            source,
+            queue: VecDeque::new(),
+            finished: false,
+            start: Location::START,
+            cur: Location::START,
+            run_start: Location::START,
+            run_end: 0,
+            unterminated: false,
+            interpolations: Vec::new(),
+            keep_trivia: false,
+            pending_trivia: Vec::new(),
+            end_trivia: Vec::new(),
+        }
+    }
+
+    // Every token also gets the whitespace and comments around it and its text as written,
+    // so tools can rebuild the source exactly, see lossless_source
+    pub fn with_trivia(source: &'a str) -> Self {
+        Scanner {
+            keep_trivia: true,
+            ..Scanner::new(source)
+        }
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        // almost all source is ascii, only decode when it isn't
+        let c = match *self.source.as_bytes().get(self.cur.index)? {
+            byte if byte.is_ascii() => byte as char,
+            _ => self.source[self.cur.index..].chars().next()?,
+        };
+        let before = self.cur;
+        self.cur.index += c.len_utf8();
+        if c == '\n' {
+            self.cur.line += 1;
+            self.cur.col = 1;
+        } else if c.is_ascii() {
+            self.cur.col += 1;
+        } else {
+            // emoji sequences and combining marks are only as wide as all of their characters
+            // together, so the last grapheme cluster of the run is measured again as it grows
+            if self.run_end != before.index {
+                self.run_start = before;
             }
-            Some(_) => {
-                self.cur.col += 1;
-                self.cur.index += 1;
+            self.run_end = self.cur.index;
+            let run = &self.source[self.run_start.index..self.cur.index];
+            let (last, cluster) = run.grapheme_indices(true).next_back().unwrap_or((0, run));
+            // the clusters before the last one are finished, the run continues from there
+            if last > 0 {
+                self.run_start.col += display_width(&run[..last]);
+                self.run_start.index += last;
             }
-            None => {}
+            self.cur.col = self.run_start.col + display_width(cluster);
+        }
+        Some(c)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.cur.index).copied()
+    }
+
+    fn peek_second(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.cur.index + 1).copied()
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        match self.peek()? {
+            byte if byte.is_ascii() => Some(byte as char),
+            _ => self.source[self.cur.index..].chars().next(),
         }
-        c
     }
 
-    fn peek(&mut self) -> Option<&char> {
-        self.source.peek()
+    // the source of the current token so far
+    fn lexeme(&self) -> &'a str {
+        &self.source[self.start.index..self.cur.index]
     }
 
-    fn emit(&mut self, tkt: TokenType) {
+    // Reads everything that belongs to a number literal, including anything malformed,
+    // so that an error covers the whole literal. Checking it is up to parse_number.
+    fn number_text(&mut self) -> &'a str {
+        let prefixed =
+            |text: &str| ["0x", "0b", "0o"].iter().any(|prefix| text.starts_with(prefix));
+        loop {
+            let text = self.lexeme();
+            match self.peek_char() {
+                Some(c) if is_identifier_continue(c) => {}
+                // `1.5` has a fraction, but `1.abs` and `1..` do not
+                Some('.')
+                    if !prefixed(text)
+                        && !text.contains(['.', 'e', 'E'])
+                        && self.peek_second().is_some_and(|c| c.is_ascii_digit()) => {}
+                Some('+' | '-') if !prefixed(text) && text.ends_with(['e', 'E']) => {}
+                _ => break,
+            }
+            self.advance();
+        }
+        self.lexeme()
+    }
+
+    fn emit(&mut self, tkt: TokenType<'a>) {
         let token = Token {
             token_type: tkt,
             start: self.start,
             end: self.cur,
+            trivia: None,
         };
-        self.tokens.push(token);
-    }
-
-    pub fn lex(&mut self) {
-        while let Some(c) = self.next() {
-            match c {
-                '%' => self.emit(TokenType::Modulo),
-                '(' => self.emit(TokenType::LeftParenthesis),
-                ')' => self.emit(TokenType::RightParenthesis),
-                '{' => self.emit(TokenType::LeftBrace),
-                '}' => self.emit(TokenType::RightBrace),
-                '[' => self.emit(TokenType::LeftBracket),
-                ']' => self.emit(TokenType::RightBracket),
-                '.' => self.emit(TokenType::Dot),
-                ';' => self.emit(TokenType::Semicolon),
-                ',' => self.emit(TokenType::Comma),
-                '+' => self.emit(TokenType::Plus),
-                '-' => self.emit(TokenType::Minus),
-                '*' => self.emit(TokenType::Star),
-                '/' => self.emit(TokenType::Slash),
-                '<' => {
-                    if self.peek() == Some(&'=') {
-                        self.next();
-                        self.emit(TokenType::LessEqual);
-                    } else {
-                        self.emit(TokenType::Less);
-                    }
+        self.queue.push_back(token);
+    }
+
+    // an invalid token that covers just a part of the current one, e.g. a bad escape in a string
+    fn emit_invalid_from(&mut self, start: Location, message: String) {
+        let token = Token {
+            token_type: TokenType::Invalid(message),
+            start,
+            end: self.cur,
+            trivia: None,
+        };
+        self.queue.push_back(token);
+    }
+
+    // `op` on its own, or `op_equal` if a '=' follows
+    fn emit_compound(&mut self, op: TokenType<'a>, op_equal: TokenType<'a>) {
+        if self.peek() == Some(b'=') {
+            self.advance();
+            self.emit(op_equal);
+        } else {
+            self.emit(op);
+        }
+    }
+
+    fn string(&mut self) {
+        let source = self.source;
+        let content_start = self.cur.index;
+        // only a string with escapes needs a decoded copy, the others borrow the source
+        let mut decoded: Option<String> = None;
+        let text = |decoded: Option<String>, end: usize| match decoded {
+            Some(decoded) => Cow::Owned(decoded),
+            None => Cow::Borrowed(&source[content_start..end]),
+        };
+        loop {
+            let escape_start = self.cur;
+            match self.advance() {
+                None => {
+                    self.emit(TokenType::Invalid("Unterminated string".to_string()));
+                    self.unterminated = true;
+                    return;
                 }
-                '>' => {
-                    if self.peek() == Some(&'=') {
-                        self.next();
-                        self.emit(TokenType::GreaterEqual);
-                    } else {
-                        self.emit(TokenType::Greater);
-                    }
+                Some('"') => break,
+                // the text so far is emitted, the expression is scanned as usual until its '}'
+                Some('$') if self.peek() == Some(b'{') => {
+                    self.advance();
+                    self.emit(TokenType::StringInterpolation(text(decoded, escape_start.index)));
+                    self.interpolations.push(0);
+                    return;
                 }
-                '!' => {
-                    if self.peek() == Some(&'=') {
-                        self.next();
-                        self.emit(TokenType::BangEqual);
-                    } else {
-                        self.emit(TokenType::Bang);
+                Some('\\') => {
+                    let decoded = decoded
+                        .get_or_insert_with(|| source[content_start..escape_start.index].to_string());
+                    match self.escape() {
+                        Some(Ok(c)) => decoded.push(c),
+                        // the rest of the string is still scanned, so the error stays local to the escape
+                        Some(Err(message)) => self.emit_invalid_from(escape_start, message),
+                        None => {
+                            self.emit(TokenType::Invalid("Unterminated string".to_string()));
+                            self.unterminated = true;
+                            return;
+                        }
                     }
                 }
-                '=' => {
-                    if self.peek() == Some(&'=') {
-                        self.next();
-                        self.emit(TokenType::EqualEqual);
-                    } else {
-                        self.emit(TokenType::Equal);
+                Some(c) => {
+                    if let Some(decoded) = &mut decoded {
+                        decoded.push(c);
                     }
                 }
-                '"' => {
-                    let mut accumulator = String::new();
-                    while self.peek() != Some(&'"') {
-                        match self.next() {
-                            None => {
-                                self.emit(TokenType::Invalid(format!(
-                                    "[lexer] unterminated string literal at eof"
-                                )));
-                                return;
-                            }
-                            Some(cc) => accumulator.push(cc),
-                        }
+            }
+        }
+        // without the closing quote
+        let end = self.cur.index - 1;
+        self.emit(TokenType::StringLiteral(text(decoded, end)));
+    }
+
+    // decodes the escape after a backslash, returns None at the end of the input
+    fn escape(&mut self) -> Option<Result<char, String>> {
+        let c = match self.advance()? {
+            'n' => '\n',
+            't' => '\t',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            'u' => return Some(self.unicode_escape()),
+            other => return Some(Err(format!("Unknown escape sequence '\\{}'", other))),
+        };
+        Some(Ok(c))
+    }
+
+    // the part of `\u{1F600}` after the 'u', one to six hex digits that name a unicode scalar value
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        if self.peek() != Some(b'{') {
+            return Err("Expected '{' after '\\u'".to_string());
+        }
+        self.advance();
+        let digits_start = self.cur.index;
+        while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.advance();
+        }
+        let digits = &self.source[digits_start..self.cur.index];
+        if self.peek() != Some(b'}') {
+            return Err("Expected hex digits and '}' in unicode escape".to_string());
+        }
+        self.advance();
+        if digits.is_empty() || digits.len() > 6 {
+            return Err("Unicode escape must have one to six hex digits".to_string());
+        }
+        let code = u32::from_str_radix(digits, 16).expect("only hex digits were read");
+        char::from_u32(code)
+            .ok_or_else(|| format!("'\\u{{{}}}' is not a valid unicode character", digits))
+    }
+
+    // r"..." or r#"..."#, any number of '#' lets the string contain '"' followed by fewer of them
+    fn at_raw_string(&self) -> bool {
+        self.source[self.cur.index..]
+            .trim_start_matches('#')
+            .starts_with('"')
+    }
+
+    fn raw_string(&mut self) {
+        let mut hashes = 0;
+        while self.advance() == Some('#') {
+            hashes += 1;
+        }
+        // the loop above has read the opening '"'
+        let content_start = self.cur.index;
+        loop {
+            match self.advance() {
+                None => {
+                    self.emit(TokenType::Invalid("Unterminated raw string".to_string()));
+                    self.unterminated = true;
+                    return;
+                }
+                Some('"') => {
+                    let content_end = self.cur.index - 1;
+                    let mut closing = 0;
+                    while closing < hashes && self.peek() == Some(b'#') {
+                        self.advance();
+                        closing += 1;
+                    }
+                    if closing == hashes {
+                        let text = &self.source[content_start..content_end];
+                        self.emit(TokenType::StringLiteral(Cow::Borrowed(text)));
+                        return;
                     }
-                    self.next();
-                    self.emit(TokenType::StringLiteral(accumulator));
                 }
-                c => {
-                    if c.is_whitespace() {
-                        // whitespace has no semantic meaning (aside from delimiting other tokens)
-                    } else if c.is_digit(10) {
-                        let mut accumulator = c.to_string();
-                        while let Some(cc) = self.peek().filter(|c| c.is_digit(10)) {
-                            accumulator.push(*cc);
-                            self.next();
-                        }
-                        if self.peek() == Some(&'.') {
-                            accumulator.push('.');
-                            while let Some(cc) = self.peek().filter(|c| c.is_digit(10)) {
-                                accumulator.push(*cc);
-                                self.next();
-                            }
-                        }
-                        match accumulator.parse::<f64>() {
-                            Ok(f) => self.emit(TokenType::NumberLiteral(f)),
-                            Err(e) => self.emit(TokenType::Invalid(format!(
-                                "[lexer] invalid number format: {}",
-                                e
-                            ))),
-                        }
-                    } else if c.is_alphabetic() || c == '_' {
-                        let mut accumulator = c.to_string();
-                        while let Some(cc) = self
-                            .peek()
-                            .filter(|c| c.is_alphabetic() || c == &&'_' || c.is_digit(10))
-                        {
-                            accumulator.push(*cc);
-                            self.next();
-                        }
-                        match accumulator.as_str() {
-                            "and" => self.emit(TokenType::And),
-                            "class" => self.emit(TokenType::Class),
-                            "else" => self.emit(TokenType::Else),
-                            "false" => self.emit(TokenType::False),
-                            "for" => self.emit(TokenType::For),
-                            "fun" => self.emit(TokenType::Fun),
-                            "if" => self.emit(TokenType::If),
-                            "nil" => self.emit(TokenType::Nil),
-                            "or" => self.emit(TokenType::Or),
-                            "print" => self.emit(TokenType::Print),
-                            "return" => self.emit(TokenType::Return),
-                            "super" => self.emit(TokenType::Super),
-                            "this" => self.emit(TokenType::This),
-                            "true" => self.emit(TokenType::True),
-                            "var" => self.emit(TokenType::Var),
-                            "while" => self.emit(TokenType::While),
-                            _ => self.emit(TokenType::Identifier(accumulator)),
-                        }
-                    } else if c == '#' {
-                        let mut accumulator = String::new();
-                        if self.peek() == Some(&'(') {
-                            let mut depth = 1;
-                            loop {
-                                match self.next() {
-                                    None => {
-                                        self.emit(TokenType::Invalid(format!(
-                                            "[lexer] unterminated block comment at eof"
-                                        )));
-                                        return;
-                                    }
-                                    Some(c) => {
-                                        accumulator.push(c);
-                                        if c == '(' {
-                                            depth += 1;
-                                        } else if c == ')' {
-                                            depth -= 1;
-                                            if depth == 0 {
-                                                break;
-                                            }
+                Some(_) => {}
+            }
+        }
+    }
+
+    // scans the next token or piece of trivia, returns false at the end of the input
+    fn scan(&mut self) -> bool {
+        self.start = self.cur;
+        let emitted = self.queue.len();
+        let Some(c) = self.advance() else {
+            // an unterminated string inside the expression already said it all
+            if !self.interpolations.is_empty() && !self.unterminated {
+                self.emit(TokenType::Invalid(
+                    "Unterminated string interpolation".to_string(),
+                ));
+                self.unterminated = true;
+            }
+            if self.keep_trivia {
+                self.end_trivia = self.attach_trailing_trivia(self.queue.len());
+            }
+            return false;
+        };
+        match c {
+            '%' => self.emit_compound(TokenType::Modulo, TokenType::ModuloEqual),
+            '(' => self.emit(TokenType::LeftParenthesis),
+            ')' => self.emit(TokenType::RightParenthesis),
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.emit(TokenType::LeftBrace);
+            }
+            // the end of an embedded expression, the string continues after it
+            '}' if self.interpolations.last() == Some(&0) => {
+                self.interpolations.pop();
+                self.string();
+            }
+            '}' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth -= 1;
+                }
+                self.emit(TokenType::RightBrace);
+            }
+            '[' => self.emit(TokenType::LeftBracket),
+            ']' => self.emit(TokenType::RightBracket),
+            '.' if self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                let text = self.number_text();
+                self.emit(TokenType::Invalid(format!(
+                    "A number can't start with '.', write '0{}' instead",
+                    text
+                )));
+            }
+            '.' => self.emit(TokenType::Dot),
+            ';' => self.emit(TokenType::Semicolon),
+            ',' => self.emit(TokenType::Comma),
+            '+' => self.emit_compound(TokenType::Plus, TokenType::PlusEqual),
+            '-' => self.emit_compound(TokenType::Minus, TokenType::MinusEqual),
+            '*' => self.emit_compound(TokenType::Star, TokenType::StarEqual),
+            '/' => self.emit_compound(TokenType::Slash, TokenType::SlashEqual),
+            '<' => {
+                if self.peek() == Some(b'=') {
+                    self.advance();
+                    self.emit(TokenType::LessEqual);
+                } else {
+                    self.emit(TokenType::Less);
+                }
+            }
+            '>' => {
+                if self.peek() == Some(b'=') {
+                    self.advance();
+                    self.emit(TokenType::GreaterEqual);
+                } else {
+                    self.emit(TokenType::Greater);
+                }
+            }
+            '!' => {
+                if self.peek() == Some(b'=') {
+                    self.advance();
+                    self.emit(TokenType::BangEqual);
+                } else {
+                    self.emit(TokenType::Bang);
+                }
+            }
+            '=' => {
+                if self.peek() == Some(b'=') {
+                    self.advance();
+                    self.emit(TokenType::EqualEqual);
+                } else {
+                    self.emit(TokenType::Equal);
+                }
+            }
+            '"' => self.string(),
+            'r' if self.at_raw_string() => self.raw_string(),
+            c => {
+                if c.is_whitespace() {
+                    // whitespace has no semantic meaning (aside from delimiting other tokens)
+                } else if c.is_ascii_digit() {
+                    let text = self.number_text();
+                    match parse_number(text) {
+                        Ok(f) => self.emit(TokenType::NumberLiteral(f)),
+                        Err(message) => self.emit(TokenType::Invalid(message)),
+                    }
+                } else if c == '_' || is_xid_start(c) {
+                    // identifiers follow UAX #31
+                    while self.peek_char().is_some_and(is_identifier_continue) {
+                        self.advance();
+                    }
+                    let name = nfc(self.lexeme());
+                    match name.as_ref() {
+                        "and" => self.emit(TokenType::And),
+                        "class" => self.emit(TokenType::Class),
+                        "else" => self.emit(TokenType::Else),
+                        "false" => self.emit(TokenType::False),
+                        "for" => self.emit(TokenType::For),
+                        "fun" => self.emit(TokenType::Fun),
+                        "if" => self.emit(TokenType::If),
+                        "nil" => self.emit(TokenType::Nil),
+                        "or" => self.emit(TokenType::Or),
+                        "print" => self.emit(TokenType::Print),
+                        "return" => self.emit(TokenType::Return),
+                        "super" => self.emit(TokenType::Super),
+                        "this" => self.emit(TokenType::This),
+                        "true" => self.emit(TokenType::True),
+                        "var" => self.emit(TokenType::Var),
+                        "while" => self.emit(TokenType::While),
+                        _ => self.emit(TokenType::Identifier(name)),
+                    }
+                } else if c == '#' {
+                    if self.peek() == Some(b'(') {
+                        // the opening parenthesis is read by the loop below
+                        let mut depth = 0;
+                        loop {
+                            match self.advance() {
+                                None => {
+                                    self.emit(TokenType::Invalid(
+                                        "Unterminated block comment".to_string(),
+                                    ));
+                                    self.unterminated = true;
+                                    break;
+                                }
+                                Some(c) => {
+                                    if c == '(' {
+                                        depth += 1;
+                                    } else if c == ')' {
+                                        depth -= 1;
+                                        if depth == 0 {
+                                            break;
                                         }
                                     }
                                 }
                             }
-                        } else {
-                            loop {
-                                match self.next() {
-                                    None | Some('\n') => break,
-                                    Some(cc) => accumulator.push(cc),
-                                }
-                            }
                         }
-                        // Maybe I will do something with the comments in the future,
-                        // for now we will just let them go...
+                        // `#(* ... *)` documents the declaration after it
+                        let text = self.lexeme();
+                        if text.len() >= 5 && text.starts_with("#(*") && text.ends_with("*)") {
+                            self.emit(TokenType::DocComment(text[3..text.len() - 2].trim()));
+                        }
+                    } else if self.peek() == Some(b'#') {
+                        while self.peek().is_some_and(|c| c != b'\n') {
+                            self.advance();
+                        }
+                        let text = &self.lexeme()[2..];
+                        let text = text.strip_prefix(' ').unwrap_or(text);
+                        self.emit(TokenType::DocComment(text.trim_end()));
                     } else {
-                        self.emit(TokenType::Invalid(format!(
-                            "[lexer] unrecognized char '{}' in line: {}, at: {}",
-                            c,
-                            self.cur.line,
-                            self.cur.col,
-                        )));
+                        // the newline is whitespace, not part of the comment
+                        while self.peek().is_some_and(|c| c != b'\n') {
+                            self.advance();
+                        }
                     }
+                } else {
+                    self.emit(TokenType::Invalid(format!("Unexpected character '{}'", c)));
                 }
             }
         }
+        if self.keep_trivia {
+            self.collect_trivia(emitted);
+        }
+        true
+    }
+
+    // files what was just read as trivia, or as the text of the tokens emitted since `emitted`
+    fn collect_trivia(&mut self, emitted: usize) {
+        let text = self.lexeme();
+        if self.queue.len() == emitted {
+            let kind = if text.starts_with("#(") {
+                TriviaKind::BlockComment
+            } else if text.starts_with('#') {
+                TriviaKind::LineComment
+            } else {
+                TriviaKind::Whitespace
+            };
+            // whitespace is read a character at a time, a run of it ends after a newline
+            match self.pending_trivia.last_mut() {
+                Some(last)
+                    if kind == TriviaKind::Whitespace
+                        && last.kind == TriviaKind::Whitespace
+                        && !last.text.ends_with('\n') =>
+                {
+                    let start = self.start.index - last.text.len();
+                    last.text = &self.source[start..self.cur.index];
+                }
+                _ => self.pending_trivia.push(Trivia { kind, text }),
+            }
+            return;
+        }
+        let leading = self.attach_trailing_trivia(emitted);
+        // errors for bad escapes come before their string and cover a part of it, they get no text
+        if let Some(token) = self.queue.back_mut() {
+            token.trivia = Some(Box::new(TokenTrivia {
+                leading,
+                text,
+                trailing: Vec::new(),
+            }));
+        }
+    }
+
+    // Pending trivia up to the end of the line belongs to the token before it,
+    // the rest is returned to lead the next token
+    fn attach_trailing_trivia(&mut self, before: usize) -> Vec<Trivia<'a>> {
+        let mut pending = std::mem::take(&mut self.pending_trivia);
+        let Some(previous) = self
+            .queue
+            .range_mut(..before)
+            .rev()
+            .find_map(|token| token.trivia.as_mut())
+        else {
+            return pending;
+        };
+        let line_end = pending
+            .iter()
+            .position(|trivia| trivia.kind == TriviaKind::Whitespace && trivia.text.ends_with('\n'))
+            .map_or(pending.len(), |i| i + 1);
+        let leading = pending.split_off(line_end);
+        previous.trailing = pending;
+        leading
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        // with trivia a token waits for the next one, that is where its trailing trivia ends
+        let wanted = if self.keep_trivia { 2 } else { 1 };
+        while self.queue.len() < wanted && !self.finished {
+            self.finished = !self.scan();
+        }
+        self.queue.pop_front()
+    }
+}
+
+// Rebuilds the exact source from the tokens and end trivia of a Scanner::with_trivia
+pub fn lossless_source(tokens: &[Token], end_trivia: &[Trivia]) -> String {
+    let mut source = String::new();
+    for trivia in tokens.iter().filter_map(|token| token.trivia.as_deref()) {
+        for leading in &trivia.leading {
+            source += leading.text;
+        }
+        source += trivia.text;
+        for trailing in &trivia.trailing {
+            source += trailing.text;
+        }
+    }
+    for trivia in end_trivia {
+        source += trivia.text;
+    }
+    source
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_alphanumeric() || c == '_'
+    } else {
+        is_xid_continue(c)
+    }
+}
+
+// Names that look the same are the same, whether an accent was typed as part of the letter or not
+fn nfc(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    match is_nfc_quick(text.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(text),
+        _ => Cow::Owned(text.nfc().collect()),
+    }
+}
+
+// Numbers are `123`, `1.5`, `1e-9`, `2.5E3`, `0xff`, `0b1010` or `0o17`,
+// and digits may be grouped with `_` as in `1_000_000`
+fn parse_number(text: &str) -> Result<f64, String> {
+    let radix = match text.get(..2) {
+        Some("0x") => Some((16, "hexadecimal")),
+        Some("0b") => Some((2, "binary")),
+        Some("0o") => Some((8, "octal")),
+        _ => None,
+    };
+    let body = if radix.is_some() { &text[2..] } else { text };
+    let is_digit = |byte: &u8| match radix {
+        Some(_) => byte.is_ascii_hexdigit(),
+        None => byte.is_ascii_digit(),
+    };
+    let bytes = body.as_bytes();
+    for (i, byte) in bytes.iter().enumerate() {
+        let between_digits =
+            i > 0 && is_digit(&bytes[i - 1]) && bytes.get(i + 1).is_some_and(is_digit);
+        if *byte == b'_' && !between_digits {
+            return Err(format!("Invalid number literal '{}': '_' must be between digits", text));
+        }
+    }
+    let digits: Cow<str> = if body.contains('_') {
+        Cow::Owned(body.replace('_', ""))
+    } else {
+        Cow::Borrowed(body)
+    };
+
+    if let Some((radix, name)) = radix {
+        if digits.is_empty() {
+            return Err(format!("Missing digits after '{}'", &text[..2]));
+        }
+        let mut value = 0.0;
+        for c in digits.chars() {
+            let Some(digit) = c.to_digit(radix) else {
+                return Err(format!("Invalid digit '{}' in {} literal '{}'", c, name, text));
+            };
+            value = value * radix as f64 + digit as f64;
+        }
+        return Ok(value);
+    }
+
+    // digits [. digits] [e|E [+|-] digits]
+    let mut chars = digits.chars().peekable();
+    skip_digits(&mut chars);
+    if chars.next_if_eq(&'.').is_some() {
+        skip_digits(&mut chars);
+    }
+    if chars.next_if(|c| *c == 'e' || *c == 'E').is_some() {
+        chars.next_if(|c| *c == '+' || *c == '-');
+        if skip_digits(&mut chars) == 0 {
+            return Err(format!("Missing digits in the exponent of '{}'", text));
+        }
+    }
+    if let Some(c) = chars.next() {
+        return Err(format!("Invalid character '{}' in number literal '{}'", c, text));
+    }
+    digits
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid number literal '{}': {}", text, e))
+}
+
+// returns how many digits were skipped
+fn skip_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> usize {
+    let mut count = 0;
+    while chars.next_if(|c| c.is_ascii_digit()).is_some() {
+        count += 1;
     }
+    count
 }
@@ -0,0 +1,46 @@
+use crate::expr::LocExpr;
+use crate::scanner::Location;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum Stmt {
+    Expression(LocExpr),
+    Print(LocExpr),
+    // the last field is the doc comment
+    Var(String, Option<LocExpr>, Option<String>),
+    Block(Vec<LocStmt>),
+    If(LocExpr, Box<LocStmt>, Option<Box<LocStmt>>),
+    While(LocExpr, Box<LocStmt>),
+    Function(Rc<FunctionDecl>),
+    Return(Option<LocExpr>),
+    Class(ClassDecl),
+}
+
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<LocStmt>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ClassDecl {
+    pub name: String,
+    pub superclass: Option<LocExpr>,
+    pub methods: Vec<Rc<FunctionDecl>>,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct LocStmt {
+    pub stmt: Stmt,
+    pub start: Location,
+    pub end: Location,
+}
+
+impl LocStmt {
+    pub fn new(stmt: Stmt, start: Location, end: Location) -> Self {
+        LocStmt { stmt, start, end }
+    }
+}
@@ -1,9 +1,11 @@
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 use TokenType::*;
 use crate::scanner::Location;
 
+// Identifiers and strings without escapes borrow their text from the source
 #[derive(Debug, PartialEq)]
-pub enum TokenType {
+pub enum TokenType<'a> {
     LeftBracket,
     RightBracket,
     LeftBrace,
@@ -17,6 +19,11 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    ModuloEqual,
     Bang,
     BangEqual,
     Equal,
@@ -25,9 +32,15 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
-    Identifier(String),
-    StringLiteral(String),
+    // in NFC, so it is only borrowed if the source already was
+    Identifier(Cow<'a, str>),
+    StringLiteral(Cow<'a, str>),
+    // the text of a string up to a `${`, the embedded expression follows and the string
+    // continues with another StringInterpolation or ends with a StringLiteral
+    StringInterpolation(Cow<'a, str>),
     NumberLiteral(f64),
+    // one `## ...` line or a whole `#(* ... *)` block, without the markers
+    DocComment(&'a str),
     And,
     Class,
     Else,
@@ -49,13 +62,39 @@ pub enum TokenType {
 }
 
 #[derive(Debug)]
-pub struct Token {
-    pub token_type: TokenType,
+pub struct Token<'a> {
+    pub token_type: TokenType<'a>,
     pub start: Location,
     pub end: Location,
+    // only filled in by Scanner::with_trivia
+    pub trivia: Option<Box<TokenTrivia<'a>>>,
 }
 
-impl<'a> Display for Token {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    // `# ...` without the newline
+    LineComment,
+    // `#( ... )`, possibly nested
+    BlockComment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenTrivia<'a> {
+    pub leading: Vec<Trivia<'a>>,
+    // the token as written, with quotes and escapes
+    pub text: &'a str,
+    // trivia up to and including the end of the token's line
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self.token_type {
             LeftBracket => write!(f, "["),
@@ -71,6 +110,11 @@ impl<'a> Display for Token {
             Semicolon => write!(f, ";"),
             Slash => write!(f, "/"),
             Star => write!(f, "*"),
+            PlusEqual => write!(f, "+="),
+            MinusEqual => write!(f, "-="),
+            StarEqual => write!(f, "*="),
+            SlashEqual => write!(f, "/="),
+            ModuloEqual => write!(f, "%="),
             Bang => write!(f, "!"),
             BangEqual => write!(f, "!="),
             Equal => write!(f, "="),
@@ -98,7 +142,9 @@ impl<'a> Display for Token {
             Modulo => write!(f, "%"),
             Identifier(id) => write!(f, "{}", id),
             StringLiteral(lit) => write!(f, "{}", lit),
+            StringInterpolation(lit) => write!(f, "{}${{", lit),
             NumberLiteral(lit) => write!(f, "{}", lit),
+            DocComment(text) => write!(f, "## {}", text),
             Invalid(reason) => write!(f, "Scanning Error: {}", reason),
         }
     }
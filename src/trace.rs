@@ -0,0 +1,43 @@
+use crate::object::Function;
+use crate::stmt::LocStmt;
+
+// What to print while compiling and running. Everything is printed to stdout,
+// and without the `trace` feature none of it is compiled in.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TraceConfig {
+    // the stack and every instruction right before the vm executes it
+    pub trace_exec: bool,
+    pub dump_ast: bool,
+    pub dump_bytecode: bool,
+}
+
+impl TraceConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.trace_exec || self.dump_ast || self.dump_bytecode
+    }
+}
+
+#[cfg(feature = "trace")]
+pub fn dump_ast(program: &[LocStmt]) {
+    println!("=== ast ===");
+    for stmt in program {
+        println!("{:#?}", stmt);
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+pub fn dump_ast(_program: &[LocStmt]) {}
+
+// disassembles the function and every function it defines
+#[cfg(feature = "trace")]
+pub fn dump_bytecode(function: &Function) {
+    crate::debug::print_chunk(&function.chunk, &function.to_string());
+    for constant in function.chunk.constants() {
+        if let crate::value::Value::Function(inner) = constant {
+            dump_bytecode(inner);
+        }
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+pub fn dump_bytecode(_function: &Function) {}
@@ -1,11 +1,42 @@
-use std::fmt::{Display, Formatter};
+use crate::object::{Callable, Function, Instance};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
 
-#[derive(PartialEq)]
+#[derive(Clone)]
 pub enum Value {
     Nil,
     Bool(bool),
     Number(f64),
     String(String),
+    Callable(Callable),
+    Function(Rc<Function>),
+    Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+impl Value {
+    // nil and false are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Callable(a), Value::Callable(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
 impl From<bool> for Value {
@@ -26,6 +57,49 @@ impl From<String> for Value {
     }
 }
 
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(value)))
+    }
+}
+
+// the position in a list of the given length that the index value points at
+pub(crate) fn list_index(index: &Value, len: usize) -> Result<usize, String> {
+    let Value::Number(index) = *index else {
+        return Err("List index must be a number".to_string());
+    };
+    if index.fract() != 0.0 {
+        return Err(format!("List index must be a whole number, got {}", index));
+    }
+    if index < 0.0 || index >= len as f64 {
+        return Err(format!(
+            "Index {} is out of bounds for a list of length {}",
+            index, len
+        ));
+    }
+    Ok(index as usize)
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "Nil"),
+            Value::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Value::Number(n) => f.debug_tuple("Number").field(n).finish(),
+            Value::String(s) => f.debug_tuple("String").field(s).finish(),
+            Value::Callable(c) => f.debug_tuple("Callable").field(c).finish(),
+            Value::Function(func) => f.debug_tuple("Function").field(func).finish(),
+            Value::Instance(instance) => {
+                f.debug_tuple("Instance").field(&instance.borrow()).finish()
+            }
+            Value::List(list) => {
+                write!(f, "List(")?;
+                fmt_list(list, f, true, &mut HashSet::new())?;
+                write!(f, ")")
+            }
+        }
+    }
+}
 
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -33,7 +107,42 @@ impl Display for Value {
             Value::Number(n) => write!(f, "{}", n),
             Value::Bool(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
-            Value::String(s) => write!(f, "\"{}\"", s),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Callable(c) => write!(f, "{}", c),
+            Value::Function(func) => write!(f, "{}", func),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+            Value::List(list) => fmt_list(list, f, false, &mut HashSet::new()),
+        }
+    }
+}
+
+// `seen` holds the lists that are being formatted further up, a list that contains
+// itself is printed as [...] the second time around
+fn fmt_list(
+    list: &Rc<RefCell<Vec<Value>>>,
+    f: &mut Formatter<'_>,
+    debug: bool,
+    seen: &mut HashSet<*const RefCell<Vec<Value>>>,
+) -> std::fmt::Result {
+    if !seen.insert(Rc::as_ptr(list)) {
+        return write!(f, "[...]");
+    }
+    write!(f, "[")?;
+    for (i, element) in list.borrow().iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match element {
+            Value::List(inner) if debug => {
+                write!(f, "List(")?;
+                fmt_list(inner, f, debug, seen)?;
+                write!(f, ")")?;
+            }
+            Value::List(inner) => fmt_list(inner, f, debug, seen)?,
+            element if debug => write!(f, "{:?}", element)?,
+            element => write!(f, "{}", element)?,
         }
     }
+    seen.remove(&Rc::as_ptr(list));
+    write!(f, "]")
 }
@@ -1,121 +1,529 @@
-use crate::chunk::{opcode, Chunk};
-use crate::compiler;
-use crate::debug::print_instruction;
-use crate::value::Value;
+use crate::chunk::{Chunk, OpCode};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::trace::{self, TraceConfig};
+use crate::object::{natives, BoundMethod, Callable, Class, Closure, Function, Instance, Upvalue};
+use crate::value::{Value, list_index};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const FRAMES_MAX: usize = 1024;
 
 pub struct Vm {
-    chunk: Chunk,
-    ip: usize,
+    frames: Vec<CallFrame>,
     stack: Vec<Value>,
+    globals: HashMap<String, Value>,
+    // upvalues that still point into the stack, ordered by their stack slot
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    trace: TraceConfig,
 }
 
-pub enum InterpretResult {
-    InterpretOK,
-    InterpretError(&'static str),
-    InterpretRuntimeError(&'static str),
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // index of the stack slot that holds the called function, its locals follow
+    slot_base: usize,
 }
 
 impl Vm {
-    pub fn new(chunk: Chunk) -> Vm {
+    pub fn with_trace(trace: TraceConfig) -> Vm {
+        let mut globals = HashMap::new();
+        for native in natives() {
+            globals.insert(
+                native.name.to_string(),
+                Value::Callable(Callable::Native(native)),
+            );
+        }
         Vm {
-            chunk,
-            ip: 0,
+            frames: Vec::with_capacity(64),
             stack: Vec::with_capacity(256),
+            globals,
+            open_upvalues: Vec::new(),
+            trace,
         }
     }
 
-    pub fn change_chunk(&mut self, chunk: Chunk) {
-        self.chunk = chunk;
-        self.ip = 0;
+    // Runs a compiled script, globals are kept from earlier runs
+    pub fn run_function(&mut self, function: Function) -> Result<Value, Box<Diagnostic>> {
+        if self.trace.dump_bytecode {
+            trace::dump_bytecode(&function);
+        }
+        let closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: Vec::new(),
+        });
+        // a previous run may have stopped halfway through with a runtime error
         self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.stack.push(Value::Callable(Callable::Closure(Rc::clone(&closure))));
+        if let Err(message) = self.call(closure, 0) {
+            return Err(self.runtime_error(message));
+        }
+        self.run()
     }
 
-    pub fn interpret(file: String) -> InterpretResult {
-        let ch = Chunk::new(8, 4);
-        let mut parser = compiler::Parser::new(&file);
-        let success = parser.compile();
-        if !success {
-            return InterpretResult::InterpretError("Error");
-        }
-        let mut vm = Vm::new(ch);
-        vm.run()
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
     }
-    pub fn run(&mut self) -> InterpretResult {
-        println!();
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    fn run(&mut self) -> Result<Value, Box<Diagnostic>> {
         loop {
-            // Disable for efficient interpretation
-            // DEBUG begin
-            print_instruction(&self.chunk, self.ip);
-            // DEBUG end
+            #[cfg(feature = "trace")]
+            if self.trace.trace_exec {
+                self.trace_instruction();
+            }
 
-            let Ok(op) = self.read_byte().try_into() else {
-                return InterpretResult::InterpretError("Invalid OpCode");
+            let op = match OpCode::try_from(self.read_byte()) {
+                Ok(op) => op,
+                Err(error) => return Err(self.runtime_error(error.to_string())),
             };
+            let operand = self.read_operand(op.operand_width());
 
-            match op {
-                opcode::RETURN => {
-                    if self.stack.len() > 0 {
-                        println!("{}", self.stack.pop().unwrap());
-                    } else {
-                        println!("No return value: stack is empty");
+            let result = match op {
+                OpCode::Return => {
+                    let result = self.stack.pop().expect("No value to return.");
+                    let frame = self.frames.pop().expect("No frame to return from.");
+                    self.close_upvalues(frame.slot_base);
+                    if self.frames.is_empty() {
+                        self.stack.pop();
+                        return Ok(result);
                     }
-                    return InterpretResult::InterpretOK;
-                }
-                opcode::CONSTANT => {
-                    let index = self.read_byte() as usize;
-                    let constant = Value::Nil; // self.chunk.constants()[index]; FIXME
-                    self.stack.push(constant);
-                }
-                opcode::NEGATE => {
-                    let Some(x) = self.stack.last_mut() else {
-                        return InterpretResult::InterpretRuntimeError(
-                            "No value to perform operation on.",
-                        );
+                    self.stack.truncate(frame.slot_base);
+                    self.stack.push(result);
+                    Ok(())
+                }
+                OpCode::Constant | OpCode::ConstantLong => {
+                    self.stack.push(self.chunk().constants()[operand].clone());
+                    Ok(())
+                }
+                OpCode::Print => {
+                    let value = self.stack.pop().expect("No value to print.");
+                    println!("{}", value);
+                    Ok(())
+                }
+                OpCode::Pop => {
+                    self.stack.pop();
+                    Ok(())
+                }
+                OpCode::DefineGlobal | OpCode::DefineGlobalLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    let value = self.stack.pop().expect("No value to define global with.");
+                    self.globals.insert(name, value);
+                    Ok(())
+                }
+                OpCode::GetGlobal | OpCode::GetGlobalLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    match self.globals.get(&name) {
+                        Some(value) => {
+                            self.stack.push(value.clone());
+                            Ok(())
+                        }
+                        None => Err(format!("Undefined variable '{}'", name)),
+                    }
+                }
+                OpCode::SetGlobal | OpCode::SetGlobalLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    let value = self.stack.last().expect("No value to assign.").clone();
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => {
+                            *slot = value;
+                            Ok(())
+                        }
+                        None => Err(format!("Undefined variable '{}'", name)),
+                    }
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slot_base + operand;
+                    self.stack.push(self.stack[slot].clone());
+                    Ok(())
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slot_base + operand;
+                    self.stack[slot] = self.stack.last().expect("No value to assign.").clone();
+                    Ok(())
+                }
+                OpCode::GetUpvalue => {
+                    let value = match &*self.frame().closure.upvalues[operand].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
                     };
-                    *x = Value::from(-1.0 * 1.0); //FIXME
-                    // TODO Could optimize here, just need to change first bit
-                }
-                opcode::ADD => self.numeric_binary_operation(|a, b| Value::from(a + b)),
-                opcode::SUBTRACT => self.numeric_binary_operation(|a, b| Value::from(a - b)),
-                opcode::MULTIPLY => self.numeric_binary_operation(|a, b| Value::from(a * b)),
-                opcode::DIVIDE => self.numeric_binary_operation(|a, b| Value::from(a / b)),
-                opcode::MODULO => self.numeric_binary_operation(|a, b| Value::from(a % b)),
-                opcode::GREATER => self.numeric_binary_operation(|a, b| Value::from(a > b)),
-                opcode::GREATER_EQUAL => self.numeric_binary_operation(|a, b| Value::from(a >= b)),
-                opcode::LESS => self.numeric_binary_operation(|a, b| Value::from(a < b)),
-                opcode::LESS_EQUAL => self.numeric_binary_operation(|a, b| Value::from(a <= b)),
-                opcode::EQUAL => self.binary_operation(|a, b| Value::from(a == b)),
-                opcode::NOT_EQUAL => self.binary_operation(|a, b| Value::from(a != b)),
-                _ => todo!(),
-            }
+                    self.stack.push(value);
+                    Ok(())
+                }
+                OpCode::SetUpvalue => {
+                    let value = self.stack.last().expect("No value to assign.").clone();
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[operand]);
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                    Ok(())
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                    Ok(())
+                }
+                OpCode::Closure | OpCode::ClosureLong => {
+                    let Value::Function(function) = self.chunk().constants()[operand].clone() else {
+                        unreachable!("Closure operand is not a function constant")
+                    };
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        if is_local {
+                            upvalues.push(self.capture_upvalue(self.frame().slot_base + index));
+                        } else {
+                            upvalues.push(Rc::clone(&self.frame().closure.upvalues[index]));
+                        }
+                    }
+                    let closure = Closure { function, upvalues };
+                    self.stack
+                        .push(Value::Callable(Callable::Closure(Rc::new(closure))));
+                    Ok(())
+                }
+                OpCode::Call => {
+                    let callee = self.stack[self.stack.len() - 1 - operand].clone();
+                    self.call_value(callee, operand)
+                }
+                OpCode::Class | OpCode::ClassLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    self.stack
+                        .push(Value::Callable(Callable::Class(Rc::new(Class::new(name)))));
+                    Ok(())
+                }
+                OpCode::Inherit => {
+                    let subclass = self.stack.pop().expect("No class to inherit into.");
+                    match (self.stack.last(), subclass) {
+                        (
+                            Some(Value::Callable(Callable::Class(superclass))),
+                            Value::Callable(Callable::Class(subclass)),
+                        ) => {
+                            subclass
+                                .methods
+                                .borrow_mut()
+                                .extend(superclass.methods.borrow().clone());
+                            Ok(())
+                        }
+                        _ => Err("Superclass must be a class".to_string()),
+                    }
+                }
+                OpCode::Method | OpCode::MethodLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    let method = self.stack.pop().expect("No method to define.");
+                    let Some(Value::Callable(Callable::Class(class))) = self.stack.last() else {
+                        unreachable!("Method defined outside of a class")
+                    };
+                    let Value::Callable(method) = method else {
+                        unreachable!("Method is not a closure")
+                    };
+                    class.methods.borrow_mut().insert(name, method);
+                    Ok(())
+                }
+                OpCode::GetProperty | OpCode::GetPropertyLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    match self.stack.pop() {
+                        Some(Value::Instance(instance)) => {
+                            let field = instance.borrow().fields.get(&name).cloned();
+                            match field {
+                                Some(value) => {
+                                    self.stack.push(value);
+                                    Ok(())
+                                }
+                                None => {
+                                    let class = Rc::clone(&instance.borrow().class);
+                                    self.bind_method(&class, &name, Value::Instance(instance))
+                                }
+                            }
+                        }
+                        _ => Err("Only instances have properties".to_string()),
+                    }
+                }
+                OpCode::SetProperty | OpCode::SetPropertyLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    let value = self.stack.pop().expect("No value to assign.");
+                    match self.stack.pop() {
+                        Some(Value::Instance(instance)) => {
+                            instance.borrow_mut().fields.insert(name, value.clone());
+                            self.stack.push(value);
+                            Ok(())
+                        }
+                        _ => Err("Only instances have fields".to_string()),
+                    }
+                }
+                OpCode::GetSuper | OpCode::GetSuperLong => {
+                    let name = self.chunk().names()[operand].clone();
+                    let Some(Value::Callable(Callable::Class(superclass))) = self.stack.pop() else {
+                        unreachable!("super is not a class")
+                    };
+                    let receiver = self.stack.pop().expect("No receiver for super.");
+                    self.bind_method(&superclass, &name, receiver)
+                }
+                OpCode::Interpolate => {
+                    let parts = self.stack.split_off(self.stack.len() - operand);
+                    let text: String = parts.iter().map(|part| part.to_string()).collect();
+                    self.stack.push(Value::String(text));
+                    Ok(())
+                }
+                OpCode::List => {
+                    let elements = self.stack.split_off(self.stack.len() - operand);
+                    self.stack.push(Value::from(elements));
+                    Ok(())
+                }
+                OpCode::GetIndex => {
+                    let index = self.stack.pop().expect("No index to read at.");
+                    match self.stack.pop() {
+                        Some(Value::List(list)) => {
+                            let list = list.borrow();
+                            list_index(&index, list.len()).map(|index| self.stack.push(list[index].clone()))
+                        }
+                        _ => Err("Only lists can be indexed".to_string()),
+                    }
+                }
+                OpCode::SetIndex => {
+                    let value = self.stack.pop().expect("No value to assign.");
+                    let index = self.stack.pop().expect("No index to assign at.");
+                    match self.stack.pop() {
+                        Some(Value::List(list)) => {
+                            let mut list = list.borrow_mut();
+                            list_index(&index, list.len()).map(|index| {
+                                list[index] = value.clone();
+                                self.stack.push(value);
+                            })
+                        }
+                        _ => Err("Only lists can be indexed".to_string()),
+                    }
+                }
+                OpCode::Dup => {
+                    let value = self.stack.last().expect("No value to duplicate.").clone();
+                    self.stack.push(value);
+                    Ok(())
+                }
+                OpCode::Dup2 => {
+                    let below = self.stack[self.stack.len() - 2].clone();
+                    let top = self.stack[self.stack.len() - 1].clone();
+                    self.stack.push(below);
+                    self.stack.push(top);
+                    Ok(())
+                }
+                OpCode::Not => {
+                    let value = self.stack.pop().expect("No value to perform operation on.");
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                    Ok(())
+                }
+                OpCode::Jump => {
+                    self.frame_mut().ip += operand;
+                    Ok(())
+                }
+                OpCode::JumpIfFalse => {
+                    if !self.stack.last().is_some_and(Value::is_truthy) {
+                        self.frame_mut().ip += operand;
+                    }
+                    Ok(())
+                }
+                OpCode::Loop => {
+                    self.frame_mut().ip -= operand;
+                    Ok(())
+                }
+                OpCode::Nil => {
+                    self.stack.push(Value::Nil);
+                    Ok(())
+                }
+                OpCode::True => {
+                    self.stack.push(Value::Bool(true));
+                    Ok(())
+                }
+                OpCode::False => {
+                    self.stack.push(Value::Bool(false));
+                    Ok(())
+                }
+                OpCode::Negate => match self.stack.last_mut() {
+                    Some(Value::Number(n)) => {
+                        *n = -*n;
+                        Ok(())
+                    }
+                    Some(_) => Err("Operand must be a number".to_string()),
+                    None => Err("No value to perform operation on.".to_string()),
+                },
+                OpCode::Add => self.add(),
+                OpCode::Subtract => self.numeric_binary_operation(|a, b| Value::from(a - b)),
+                OpCode::Multiply => self.numeric_binary_operation(|a, b| Value::from(a * b)),
+                OpCode::Divide => self.numeric_binary_operation(|a, b| Value::from(a / b)),
+                OpCode::Modulo => self.numeric_binary_operation(|a, b| Value::from(a % b)),
+                OpCode::Greater => self.numeric_binary_operation(|a, b| Value::from(a > b)),
+                OpCode::GreaterEqual => self.numeric_binary_operation(|a, b| Value::from(a >= b)),
+                OpCode::Less => self.numeric_binary_operation(|a, b| Value::from(a < b)),
+                OpCode::LessEqual => self.numeric_binary_operation(|a, b| Value::from(a <= b)),
+                OpCode::Equal => self.binary_operation(|a, b| Value::from(a == b)),
+                OpCode::NotEqual => self.binary_operation(|a, b| Value::from(a != b)),
+            };
 
-            // DEBUG begin
-            print!("Stack: ");
-            for val in self.stack.iter() {
-                print!("[ {} ]", val)
+            if let Err(message) = result {
+                return Err(self.runtime_error(message));
             }
-            println!();
-            println!();
-            // DEBUG end
         }
     }
 
+    // prints the stack as the next instruction will find it, followed by that instruction
+    #[cfg(feature = "trace")]
+    fn trace_instruction(&self) {
+        print!("          ");
+        for value in &self.stack {
+            print!("[ {} ]", value);
+        }
+        println!();
+        crate::debug::print_instruction(self.chunk(), self.frame().ip);
+    }
+
+    // replaces the receiver with the named method of its class, bound to that receiver
+    fn bind_method(&mut self, class: &Class, name: &str, receiver: Value) -> Result<(), String> {
+        let Some(Callable::Closure(method)) = class.find_method(name) else {
+            return Err(format!("Undefined property '{}'", name));
+        };
+        let bound = BoundMethod { receiver, method };
+        self.stack
+            .push(Value::Callable(Callable::BoundMethod(Rc::new(bound))));
+        Ok(())
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("No active call frame.")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("No active call frame.")
+    }
+
+    fn chunk(&self) -> &Chunk {
+        &self.frame().closure.function.chunk
+    }
+
     fn read_byte(&mut self) -> u8 {
-        let result = self.chunk.code()[self.ip];
-        self.ip += 1;
+        let frame = self.frame_mut();
+        let result = frame.closure.function.chunk.code()[frame.ip];
+        frame.ip += 1;
         result
     }
 
-    fn binary_operation<F>(&mut self, callback: F)
+    // the operand of the instruction that was just read, `width` bytes wide
+    fn read_operand(&mut self, width: usize) -> usize {
+        let frame = self.frame_mut();
+        let operand = frame.closure.function.chunk.read_operand(frame.ip, width);
+        frame.ip += width;
+        operand
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), String> {
+        match callee {
+            Value::Callable(Callable::Closure(closure)) => self.call(closure, arg_count),
+            Value::Callable(Callable::Native(native)) => {
+                if arg_count != native.arity {
+                    return Err(format!(
+                        "Expected {} arguments but got {}",
+                        native.arity, arg_count
+                    ));
+                }
+                let args_start = self.stack.len() - arg_count;
+                let result = (native.function)(&self.stack[args_start..]);
+                self.stack.truncate(args_start - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            Value::Callable(Callable::Class(class)) => {
+                let callee_slot = self.stack.len() - arg_count - 1;
+                let instance = Instance::new(Rc::clone(&class));
+                self.stack[callee_slot] = Value::Instance(Rc::new(RefCell::new(instance)));
+                match class.find_method("init") {
+                    Some(Callable::Closure(init)) => self.call(init, arg_count),
+                    _ if arg_count != 0 => Err(format!("Expected 0 arguments but got {}", arg_count)),
+                    _ => Ok(()),
+                }
+            }
+            Value::Callable(Callable::BoundMethod(bound)) => {
+                let callee_slot = self.stack.len() - arg_count - 1;
+                self.stack[callee_slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), arg_count)
+            }
+            _ => Err("Can only call functions and classes".to_string()),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, arg_count: usize) -> Result<(), String> {
+        if arg_count != closure.function.arity {
+            return Err(format!(
+                "Expected {} arguments but got {}",
+                closure.function.arity, arg_count
+            ));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err("Stack overflow".to_string());
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slot_base: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        let position = self
+            .open_upvalues
+            .partition_point(|u| matches!(*u.borrow(), Upvalue::Open(s) if s < slot));
+        self.open_upvalues.insert(position, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // moves every captured variable at or above the given stack slot off the stack
+    fn close_upvalues(&mut self, from_slot: usize) {
+        let position = self
+            .open_upvalues
+            .partition_point(|u| matches!(*u.borrow(), Upvalue::Open(s) if s < from_slot));
+        for upvalue in self.open_upvalues.drain(position..) {
+            let mut upvalue = upvalue.borrow_mut();
+            if let Upvalue::Open(slot) = *upvalue {
+                *upvalue = Upvalue::Closed(self.stack[slot].clone());
+            }
+        }
+    }
+
+    fn add(&mut self) -> Result<(), String> {
+        let b = self.stack.pop().expect("No value to perform operation on.");
+        let a = self.stack.pop().expect("No value to perform operation on.");
+
+        match (a, b) {
+            (Value::Number(c), Value::Number(d)) => self.stack.push(Value::from(c + d)),
+            (Value::String(c), Value::String(d)) => self.stack.push(Value::from(c + &d)),
+            _ => return Err("Operands must be two numbers or two strings".to_string()),
+        }
+        Ok(())
+    }
+
+    fn binary_operation<F>(&mut self, callback: F) -> Result<(), String>
     where
         F: Fn(Value, Value) -> Value,
     {
         let b = self.stack.pop().expect("No value to perform operation on.");
         let a = self.stack.pop().expect("No value to perform operation on.");
         self.stack.push(callback(a, b));
+        Ok(())
     }
 
-    fn numeric_binary_operation<F>(&mut self, callback: F)
+    fn numeric_binary_operation<F>(&mut self, callback: F) -> Result<(), String>
     where
         F: Fn(f64, f64) -> Value,
     {
@@ -125,12 +533,19 @@ impl Vm {
         match (a, b) {
             (Value::Number(c), Value::Number(d)) => {
                 self.stack.push(callback(c, d));
+                Ok(())
             }
-            _ => todo!(),
+            _ => Err("Operands must be numbers".to_string()),
         }
     }
 
-    fn runtime_error(&mut self, message: &'static str) {
-        println!("[ERROR] in line {}: {}", self.ip, message);
+    // points at the instruction that failed and lists the calls that led to it
+    fn runtime_error(&self, message: String) -> Box<Diagnostic> {
+        let location = self.chunk().location_at(self.frame().ip - 1);
+        let calls = self.frames.iter().rev().map(|frame| {
+            let function = &frame.closure.function;
+            (function.to_string(), function.chunk.location_at(frame.ip - 1).line)
+        });
+        Box::new(Diagnostic::error(message, Span::point(location)).with_trace(calls))
     }
 }
@@ -0,0 +1,76 @@
+use rox::{Backend, Engine, Error};
+
+const DEPTH: &str = "fun d(n) { if (n == 0) return 0; return 1 + d(n - 1); }\n";
+
+#[test]
+fn deep_recursion_runs_on_both_backends() {
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let value = Engine::new(backend)
+            .eval(&format!("{DEPTH}d(1000);"))
+            .unwrap();
+        assert_eq!(value.unwrap().to_string(), "1000", "{:?}", backend);
+    }
+}
+
+#[test]
+fn runaway_recursion_is_a_runtime_error() {
+    let source = "fun f(n) {\n  return f(n + 1);\n}\nf(0);";
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let mut engine = Engine::new(backend);
+        let Err(Error::Runtime { diagnostic, .. }) = engine.eval(source) else {
+            panic!("{:?} didn't overflow", backend);
+        };
+        assert_eq!(diagnostic.message, "Stack overflow", "{:?}", backend);
+        assert_eq!(diagnostic.span.start.line, 2, "{:?}", backend);
+        assert_eq!(
+            diagnostic.notes,
+            [
+                "in <fn f> at line 2",
+                "... 1022 more frames in <fn f>",
+                "in <script> at line 4"
+            ],
+            "{:?}",
+            backend
+        );
+        // the engine can still run code afterwards
+        let value = engine.eval(&format!("{DEPTH}d(500);")).unwrap();
+        assert_eq!(value.unwrap().to_string(), "500", "{:?}", backend);
+    }
+}
+
+#[test]
+fn long_call_traces_are_capped() {
+    let source = "fun f(n) {\n  if (n == 0) return nil + 1;\n  return g(n - 1);\n}\n\
+                  fun g(n) {\n  return f(n - 1);\n}\nf(100);";
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let Err(Error::Runtime { diagnostic, .. }) = Engine::new(backend).eval(source) else {
+            panic!("{:?} didn't fail", backend);
+        };
+        assert_eq!(diagnostic.notes.len(), 17, "{:?}", backend);
+        assert_eq!(diagnostic.notes[0], "in <fn f> at line 2", "{:?}", backend);
+        assert_eq!(diagnostic.notes[1], "in <fn g> at line 6", "{:?}", backend);
+        // 101 calls and the script, 16 of them are listed
+        assert_eq!(diagnostic.notes[16], "... 86 more frames", "{:?}", backend);
+    }
+}
+
+#[test]
+fn both_backends_list_the_same_calls() {
+    let source = "class A {\n  init(x) {\n    this.x = x + nil;\n  }\n}\n\
+                  fun make(x) {\n  return A(x);\n}\nprint 1;\nmake(2);";
+    let notes = [Backend::Interpreter, Backend::Vm].map(|backend| {
+        let Err(Error::Runtime { diagnostic, .. }) = Engine::new(backend).eval(source) else {
+            panic!("{:?} didn't fail", backend);
+        };
+        diagnostic.notes
+    });
+    assert_eq!(
+        notes[0],
+        [
+            "in <fn init> at line 3",
+            "in <fn make> at line 7",
+            "in <script> at line 10"
+        ]
+    );
+    assert_eq!(notes[0], notes[1]);
+}
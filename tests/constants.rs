@@ -0,0 +1,92 @@
+use rox::codegen::CodeGen;
+use rox::compiler::Parser;
+use rox::value::Value;
+use rox::{Backend, Engine};
+
+// a script that sums `count` distinct literals and then reads back the last of `count` strings
+fn many_literals(count: usize) -> String {
+    let mut source = String::from("var sum = 0;\n");
+    for i in 0..count {
+        source += &format!("sum = sum + {}.5;\n", i);
+    }
+    source += "var last;\n";
+    for i in 0..count {
+        source += &format!("last = \"string {}\";\n", i);
+    }
+    source += "[sum, last];\n";
+    source
+}
+
+#[test]
+fn chunks_hold_thousands_of_constants() {
+    let source = many_literals(5000);
+    let program = Parser::new(&source).compile().unwrap();
+    let function = CodeGen::generate(&program).unwrap();
+    // the numbers, the strings and the 0 that sum starts at
+    assert_eq!(function.chunk.constants().len(), 10001);
+    assert_eq!(function.chunk.names(), ["sum", "last"]);
+}
+
+#[test]
+fn both_backends_read_every_literal() {
+    let source = many_literals(5000);
+    // 0.5 + 1.5 + ... + 4999.5
+    let expected = "[12500000, string 4999]";
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let value = Engine::new(backend).eval(&source).unwrap().unwrap();
+        assert_eq!(value.to_string(), expected, "{:?}", backend);
+    }
+}
+
+#[test]
+fn literals_past_the_first_256_keep_their_value() {
+    let mut source = String::new();
+    for i in 0..1000 {
+        source += &format!("var v{} = {};\n", i % 10, i);
+    }
+    let mut engine = Engine::new(Backend::Vm);
+    engine.eval(&source).unwrap();
+    assert_eq!(engine.get_global("v9"), Some(Value::Number(999.0)));
+    assert_eq!(engine.get_global("v0"), Some(Value::Number(990.0)));
+}
+
+#[test]
+fn closures_past_the_first_256_constants() {
+    let mut source = String::new();
+    for i in 0..300 {
+        source += &format!("print {};\n", i);
+    }
+    source += "var x = 7;\nfun f() { return x + 1; }\nf();\n";
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let value = Engine::new(backend).eval(&source).unwrap().unwrap();
+        assert_eq!(value.to_string(), "8", "{:?}", backend);
+    }
+}
+
+#[test]
+fn chunks_hold_hundreds_of_names() {
+    let mut source = String::new();
+    for i in 0..300 {
+        source += &format!("var g{i} = {i};\n");
+    }
+    // past the first 256 names the class, its methods and super use the long forms too
+    source += "class A {\n";
+    for i in 0..300 {
+        source += &format!("  m{i}() {{ return {i}; }}\n");
+    }
+    source += "}\nclass B < A {\n  total() {\n    var total = 0;\n";
+    for i in 0..300 {
+        source += &format!("    this.p{i} = g{i};\n    total += this.p{i};\n");
+    }
+    source += "    return total + super.m299();\n  }\n}\n";
+    source += "g299 = g299 + 1;\n[B().total(), B().m298(), g299];\n";
+
+    let program = Parser::new(&source).compile().unwrap();
+    let function = CodeGen::generate(&program).unwrap();
+    assert!(function.chunk.names().len() > 600);
+    for backend in [Backend::Interpreter, Backend::Vm] {
+        let value = Engine::new(backend).eval(&source).unwrap().unwrap();
+        // 0 + 1 + ... + 298 + 300, and 299 from super
+        assert_eq!(value.to_string(), "[45150, 298, 300]", "{:?}", backend);
+    }
+}
@@ -0,0 +1,19 @@
+use rox::compiler::Parser;
+
+fn render(source: &str) -> String {
+    let diagnostics = Parser::new(source).compile().unwrap_err();
+    diagnostics[0].render("test.lox", source, false)
+}
+
+#[test]
+fn carets_line_up_under_wide_glyphs() {
+    let rendered = render("print \"日本\" +;");
+    assert!(rendered.contains(" --> test.lox:1:15\n"), "{}", rendered);
+    assert!(rendered.contains("1 | print \"日本\" +;\n  |               ^ found ';'"), "{}", rendered);
+}
+
+#[test]
+fn carets_line_up_after_combining_marks_and_tabs() {
+    let rendered = render("\tvar cafe\u{301} = ;");
+    assert!(rendered.contains("\tvar cafe\u{301} = ;\n  | \t           ^ found ';'"), "{}", rendered);
+}
@@ -0,0 +1,93 @@
+use rox::compiler::Parser;
+use serde_json::json;
+
+fn ast(source: &str) -> serde_json::Value {
+    rox::json::ast(&Parser::new(source).compile().unwrap())
+}
+
+#[test]
+fn tokens_have_type_lexeme_and_locations() {
+    assert_eq!(
+        rox::json::tokens("x = \"a\\tb\";\n1_0"),
+        json!([
+            {
+                "type": "Identifier",
+                "lexeme": "x",
+                "start": { "line": 1, "col": 1, "offset": 0 },
+                "end": { "line": 1, "col": 2, "offset": 1 },
+                "name": "x",
+            },
+            {
+                "type": "Equal",
+                "lexeme": "=",
+                "start": { "line": 1, "col": 3, "offset": 2 },
+                "end": { "line": 1, "col": 4, "offset": 3 },
+            },
+            {
+                "type": "StringLiteral",
+                "lexeme": "\"a\\tb\"",
+                "start": { "line": 1, "col": 5, "offset": 4 },
+                "end": { "line": 1, "col": 11, "offset": 10 },
+                "value": "a\tb",
+            },
+            {
+                "type": "Semicolon",
+                "lexeme": ";",
+                "start": { "line": 1, "col": 11, "offset": 10 },
+                "end": { "line": 1, "col": 12, "offset": 11 },
+            },
+            {
+                "type": "NumberLiteral",
+                "lexeme": "1_0",
+                "start": { "line": 2, "col": 1, "offset": 12 },
+                "end": { "line": 2, "col": 4, "offset": 15 },
+                "value": 10.0,
+            },
+        ])
+    );
+}
+
+#[test]
+fn invalid_tokens_carry_their_message() {
+    let tokens = rox::json::tokens("0x");
+    assert_eq!(tokens[0]["type"], "Invalid");
+    assert_eq!(tokens[0]["message"], "Missing digits after '0x'");
+}
+
+#[test]
+fn ast_nodes_have_kind_and_locations() {
+    assert_eq!(
+        ast("print -a;"),
+        json!([{
+            "kind": "Print",
+            "start": { "line": 1, "col": 1, "offset": 0 },
+            "end": { "line": 1, "col": 10, "offset": 9 },
+            "expression": {
+                "kind": "Unary",
+                "start": { "line": 1, "col": 7, "offset": 6 },
+                "end": { "line": 1, "col": 9, "offset": 8 },
+                "operator": "-",
+                "operand": {
+                    "kind": "Variable",
+                    "start": { "line": 1, "col": 8, "offset": 7 },
+                    "end": { "line": 1, "col": 9, "offset": 8 },
+                    "name": "a",
+                },
+            },
+        }])
+    );
+}
+
+#[test]
+fn declarations_keep_their_docs() {
+    let program = ast("## Greets.\nclass A < B {\n  ## Says hi.\n  hi(name) { return 1 <= 2; }\n}");
+    let class = &program[0];
+    assert_eq!(class["kind"], "Class");
+    assert_eq!(class["doc"], "Greets.");
+    assert_eq!(class["superclass"]["name"], "B");
+    let method = &class["methods"][0];
+    assert_eq!(method["kind"], "Function");
+    assert_eq!(method["doc"], "Says hi.");
+    assert_eq!(method["params"], json!(["name"]));
+    assert_eq!(method["body"][0]["value"]["operator"], "<=");
+}
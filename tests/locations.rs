@@ -0,0 +1,77 @@
+use rox::{Backend, Engine, Error};
+
+// runtime errors far into a chunk still point at the right line and column
+#[test]
+fn runtime_errors_deep_into_a_chunk() {
+    let mut source = String::from("fun fail(x) {\n");
+    for i in 0..2000 {
+        source += &format!("  x = x + {};\n", i);
+    }
+    source += "  return x + nil;\n}\n";
+    for i in 0..2000 {
+        source += &format!("var v = {} * 2;\n", i);
+    }
+    source += "  fail(1);\n";
+
+    let Err(Error::Runtime { diagnostic, .. }) = Engine::new(Backend::Vm).eval(&source) else {
+        panic!("expected a runtime error");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "Operands must be two numbers or two strings"
+    );
+    assert_eq!(
+        (diagnostic.span.start.line, diagnostic.span.start.col),
+        (2002, 14)
+    );
+    assert_eq!(
+        diagnostic.notes,
+        ["in <fn fail> at line 2002", "in <script> at line 4004"]
+    );
+}
+
+// the start of the error as line:col, on the given backend
+fn error_start(backend: Backend, source: &str) -> String {
+    match Engine::new(backend).eval(source) {
+        Err(Error::Runtime { diagnostic, .. }) => {
+            format!(
+                "{}:{}",
+                diagnostic.span.start.line, diagnostic.span.start.col
+            )
+        }
+        _ => panic!("{:?} didn't fail on {:?}", backend, source),
+    }
+}
+
+#[test]
+fn both_backends_blame_the_same_place() {
+    // operator errors point at the right operand, index errors at the index
+    let cases = [
+        ("print \"x\" + 1;", "1:13"),
+        ("print nil + 1;", "1:13"),
+        ("print 1 - \"a\";", "1:11"),
+        ("print (1 + 2) * nil;", "1:17"),
+        ("print 1 +\n  nil;", "2:3"),
+        ("print nil < 1;", "1:13"),
+        ("print -\"x\";", "1:8"),
+        ("var a = 1; a += \"x\";", "1:17"),
+        ("var l = [1]; l[0] += nil;", "1:22"),
+        ("print [1][5];", "1:11"),
+        ("print nil[0];", "1:11"),
+        ("var l = [1]; l[\"a\"] = 2;", "1:16"),
+        ("print nil.x;", "1:7"),
+        ("fun f(a) {} f();", "1:13"),
+        ("print undefined;", "1:7"),
+    ];
+    for (source, expected) in cases {
+        for backend in [Backend::Interpreter, Backend::Vm] {
+            assert_eq!(
+                error_start(backend, source),
+                expected,
+                "{:?} on {:?}",
+                backend,
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use rox::chunk::{InvalidOpCode, OpCode};
+
+#[test]
+fn every_opcode_decodes_to_itself() {
+    let mut count = 0;
+    for byte in 0..=u8::MAX {
+        if let Ok(op) = OpCode::try_from(byte) {
+            assert_eq!(op as u8, byte, "{}", op.name());
+            count += 1;
+        }
+    }
+    // the opcodes are numbered without gaps
+    for byte in 0..count {
+        assert!(OpCode::try_from(byte).is_ok(), "{}", byte);
+    }
+}
+
+#[test]
+fn unknown_bytes_are_rejected() {
+    assert_eq!(OpCode::try_from(u8::MAX), Err(InvalidOpCode(u8::MAX)));
+    assert_eq!(InvalidOpCode(200).to_string(), "Invalid opcode 200");
+}
+
+#[test]
+fn operand_widths() {
+    assert_eq!(OpCode::Return.operand_width(), 0);
+    assert_eq!(OpCode::Constant.operand_width(), 1);
+    assert_eq!(OpCode::Jump.operand_width(), 2);
+    assert_eq!(OpCode::ConstantLong.operand_width(), 3);
+    assert_eq!(OpCode::ClosureLong.name(), "CLOSURE_LONG");
+}
+
+#[test]
+fn long_forms_take_three_byte_operands() {
+    for byte in 0..=u8::MAX {
+        let Ok(op) = OpCode::try_from(byte) else {
+            continue;
+        };
+        if let Some(long) = op.long_form() {
+            assert_eq!(op.operand_width(), 1, "{}", op.name());
+            assert_eq!(long.operand_width(), 3, "{}", long.name());
+            assert_eq!(long.name(), format!("{}_LONG", op.name()));
+        }
+    }
+}
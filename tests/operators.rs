@@ -0,0 +1,178 @@
+use rox::value::Value;
+use rox::{Backend, Engine, Error};
+
+// runs the source on both backends, they have to agree
+fn eval(source: &str) -> Result<String, String> {
+    let run = |backend| {
+        Engine::new(backend)
+            .eval(source)
+            .map(|value| value.map_or(String::new(), |value| value.to_string()))
+            .map_err(|err| match err {
+                Error::Runtime { diagnostic, .. } => diagnostic.message.clone(),
+                err => panic!("{:?} didn't compile: {}", source, err),
+            })
+    };
+    let interpreter = run(Backend::Interpreter);
+    assert_eq!(
+        interpreter,
+        run(Backend::Vm),
+        "backends disagree on {:?}",
+        source
+    );
+    interpreter
+}
+
+#[test]
+fn not() {
+    assert_eq!(eval("!true;"), Ok("false".to_string()));
+    assert_eq!(eval("!nil;"), Ok("true".to_string()));
+    assert_eq!(eval("!!0;"), Ok("true".to_string()));
+}
+
+#[test]
+fn lists_and_indexing() {
+    assert_eq!(eval("[];"), Ok("[]".to_string()));
+    assert_eq!(eval("[1, \"a\", [2]];"), Ok("[1, a, [2]]".to_string()));
+    assert_eq!(eval("[1, 2, 3][1];"), Ok("2".to_string()));
+    assert_eq!(
+        eval("var l = [[1, 2]]; l[0][1] = 5; l;"),
+        Ok("[[1, 5]]".to_string())
+    );
+    // lists are shared, not copied
+    assert_eq!(
+        eval("var a = [1]; var b = a; b[0] = 2; a == b and a[0] == 2;"),
+        Ok("true".to_string())
+    );
+}
+
+#[test]
+fn bad_indexes_are_runtime_errors() {
+    assert_eq!(
+        eval("[1][1];"),
+        Err("Index 1 is out of bounds for a list of length 1".to_string())
+    );
+    assert_eq!(
+        eval("[1][-1];"),
+        Err("Index -1 is out of bounds for a list of length 1".to_string())
+    );
+    assert_eq!(
+        eval("[1][0.5];"),
+        Err("List index must be a whole number, got 0.5".to_string())
+    );
+    assert_eq!(
+        eval("[1][\"0\"];"),
+        Err("List index must be a number".to_string())
+    );
+    assert_eq!(
+        eval("nil[0];"),
+        Err("Only lists can be indexed".to_string())
+    );
+}
+
+#[test]
+fn compound_assignment() {
+    assert_eq!(eval("var a = 1; a += 2; a;"), Ok("3".to_string()));
+    assert_eq!(
+        eval("var a = 7; a -= 2; a *= 3; a /= 5; a %= 2; a;"),
+        Ok("1".to_string())
+    );
+    assert_eq!(eval("var s = \"a\"; s += \"b\";"), Ok("ab".to_string()));
+    assert_eq!(
+        eval("fun f() { var a = 1; a += 1; return a; } f();"),
+        Ok("2".to_string())
+    );
+    assert_eq!(
+        eval("fun f() { var a = 1; fun g() { a *= 10; } g(); return a; } f();"),
+        Ok("10".to_string())
+    );
+    assert_eq!(
+        eval("class C {} var c = C(); c.x = 1; c.x += 4; c.x;"),
+        Ok("5".to_string())
+    );
+    assert_eq!(
+        eval("var l = [1, 2]; l[1] -= 5; l;"),
+        Ok("[1, -3]".to_string())
+    );
+}
+
+#[test]
+fn compound_assignment_evaluates_its_target_once() {
+    let source = "
+        var calls = 0;
+        var l = [1, 2];
+        class C {}
+        var c = C();
+        c.x = 1;
+        fun list() { calls += 1; return l; }
+        fun index() { calls += 1; return 1; }
+        fun object() { calls += 1; return c; }
+        list()[index()] += 10;
+        object().x *= 3;
+        [calls, l[1], c.x];
+    ";
+    assert_eq!(eval(source), Ok("[3, 12, 3]".to_string()));
+}
+
+#[test]
+fn compound_assignment_checks_types() {
+    assert_eq!(
+        eval("var a = 1; a += \"x\";"),
+        Err("Operands must be two numbers or two strings".to_string())
+    );
+    assert_eq!(
+        eval("var l = [nil]; l[0] -= 1;"),
+        Err("Operands must be numbers".to_string())
+    );
+    assert_eq!(
+        eval("var a; a.x += 1;"),
+        Err("Only instances have properties".to_string())
+    );
+    assert_eq!(eval("b += 1;"), Err("Undefined variable 'b'".to_string()));
+}
+
+#[test]
+fn compound_assignment_needs_a_target() {
+    for source in ["1 += 2;", "var a; a + 1 -= 2;", "[1] += [2];"] {
+        assert!(
+            matches!(
+                Engine::new(Backend::Interpreter).eval(source),
+                Err(Error::Compile { .. })
+            ),
+            "{:?} should not compile",
+            source
+        );
+    }
+}
+
+#[test]
+fn lists_are_values() {
+    let mut engine = Engine::new(Backend::Vm);
+    engine.eval("var l = [1, 2];").unwrap();
+    let Some(Value::List(list)) = engine.get_global("l") else {
+        panic!("l is not a list");
+    };
+    assert_eq!(*list.borrow(), [Value::Number(1.0), Value::Number(2.0)]);
+}
+
+#[test]
+fn values_have_a_debug_form() {
+    let mut engine = Engine::new(Backend::Interpreter);
+    let source = "class P {} var p = P(); p.y = 1; p.x = p; fun f() {} [nil, true, 1.5, \"s\", p, f, clock];";
+    let value = engine.eval(source).unwrap().unwrap();
+    assert_eq!(
+        format!("{:?}", value),
+        "List([Nil, Bool(true), Number(1.5), String(\"s\"), \
+         Instance(Instance { class: \"P\", fields: [\"x\", \"y\"] }), \
+         Callable(LoxFunction(<fn f>)), Callable(Native(<native fn clock>))])"
+    );
+}
+
+#[test]
+fn lists_that_contain_themselves_can_be_printed() {
+    let source = "var a = [1]; a[0] = a; var b = [a, a]; [a, b];";
+    assert_eq!(eval(source), Ok("[[[...]], [[[...]], [[...]]]]".to_string()));
+
+    let mut engine = Engine::new(Backend::Vm);
+    let value = engine.eval("var a = [1, nil]; a[1] = a; a;").unwrap().unwrap();
+    assert_eq!(format!("{:?}", value), "List([Number(1.0), List([...])])");
+}
@@ -0,0 +1,81 @@
+use rox::compiler::Parser;
+use rox::stmt::Stmt;
+
+#[test]
+fn doc_comments_attach_to_declarations() {
+    let source = "\
+## Adds two numbers.
+## Returns their sum.
+fun add(a, b) { return a + b; }
+#(* A point. *)
+class Point {
+  ## Makes a point.
+  init(x, y) {}
+  sum() {}
+}
+## The answer.
+var answer = 42;
+var undocumented;
+";
+    let program = Parser::new(source).compile().unwrap();
+    let [add, point, answer, undocumented] = program.as_slice() else {
+        panic!("expected four declarations, got {:?}", program);
+    };
+    let Stmt::Function(add) = &add.stmt else {
+        panic!()
+    };
+    assert_eq!(
+        add.doc.as_deref(),
+        Some("Adds two numbers.\nReturns their sum.")
+    );
+    let Stmt::Class(point) = &point.stmt else {
+        panic!()
+    };
+    assert_eq!(point.doc.as_deref(), Some("A point."));
+    assert_eq!(point.methods[0].doc.as_deref(), Some("Makes a point."));
+    assert_eq!(point.methods[1].doc, None);
+    assert!(matches!(&answer.stmt, Stmt::Var(_, _, Some(doc)) if doc == "The answer."));
+    assert!(matches!(&undocumented.stmt, Stmt::Var(_, _, None)));
+}
+
+#[test]
+fn stray_doc_comments_are_ignored() {
+    let source = "print 1 + ## not a declaration\n2;\nvar a = 1; ## trailing\nprint a;\n##";
+    let program = Parser::new(source).compile().unwrap();
+    assert_eq!(program.len(), 3);
+}
+
+fn errors(source: &str) -> Vec<String> {
+    match Parser::new(source).compile() {
+        Ok(_) => Vec::new(),
+        Err(diagnostics) => diagnostics.into_iter().map(|d| d.message).collect(),
+    }
+}
+
+#[test]
+fn locals_are_checked_before_either_backend_runs() {
+    let redeclared = "Already a variable with this name in this scope";
+    let own_initializer = "Can't read local variable in its own initializer";
+    assert_eq!(errors("{ var a = 1; var a = 2; }"), [redeclared]);
+    assert_eq!(errors("fun f(a, a) {}"), [redeclared]);
+    assert_eq!(errors("fun f(a) { var a = 1; }"), [redeclared]);
+    assert_eq!(errors("{ fun g() {} class g {} }"), [redeclared]);
+    assert_eq!(errors("{ var a = a; }"), [own_initializer]);
+    // the outer `a` doesn't count, the initializer would read the new one
+    assert_eq!(errors("var a = 1; { var a = a + 1; }"), [own_initializer]);
+    assert_eq!(
+        errors("{ var a = 1; { var b = (a = a); } }"),
+        Vec::<String>::new()
+    );
+
+    // globals can be declared again, and inner scopes can shadow
+    assert_eq!(errors("var a = 1; var a = a;"), Vec::<String>::new());
+    assert_eq!(
+        errors("{ var a = 1; { var a = 2; } }"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        errors("for (var i = 0; i < 1; i = i + 1) { var i = 2; }"),
+        Vec::<String>::new()
+    );
+}
@@ -0,0 +1,345 @@
+use rox::diagnostic::Span;
+use rox::scanner::{Scanner, lossless_source};
+use rox::token::TokenType::{self, *};
+use rox::token::{Token, Trivia, TriviaKind};
+use std::borrow::Cow;
+
+fn lex(source: &str) -> Vec<TokenType<'_>> {
+    Scanner::new(source).map(|token| token.token_type).collect()
+}
+
+fn number(source: &str) -> f64 {
+    match lex(source).as_slice() {
+        [NumberLiteral(value)] => *value,
+        other => panic!("{:?} lexed as {:?}", source, other),
+    }
+}
+
+// the message of the single invalid token in the source
+fn error(source: &str) -> String {
+    let invalid: Vec<String> = lex(source)
+        .into_iter()
+        .filter_map(|token| match token {
+            Invalid(message) => Some(message),
+            _ => None,
+        })
+        .collect();
+    match invalid.as_slice() {
+        [message] => message.clone(),
+        other => panic!("{:?} should have one error, got {:?}", source, other),
+    }
+}
+
+#[test]
+fn integers() {
+    assert_eq!(number("0"), 0.0);
+    assert_eq!(number("42"), 42.0);
+    assert_eq!(number("007"), 7.0);
+}
+
+#[test]
+fn fractions() {
+    assert_eq!(number("1.5"), 1.5);
+    assert_eq!(number("0.25"), 0.25);
+    assert_eq!(number("10.0"), 10.0);
+}
+
+#[test]
+fn dot_after_a_number_is_not_a_fraction() {
+    assert_eq!(
+        lex("1.abs"),
+        vec![NumberLiteral(1.0), Dot, Identifier("abs".into())]
+    );
+    assert_eq!(lex("1."), vec![NumberLiteral(1.0), Dot]);
+}
+
+#[test]
+fn exponents() {
+    assert_eq!(number("1e3"), 1000.0);
+    assert_eq!(number("1E3"), 1000.0);
+    assert_eq!(number("1e-9"), 1e-9);
+    assert_eq!(number("1e+2"), 100.0);
+    assert_eq!(number("2.5e-3"), 0.0025);
+}
+
+#[test]
+fn digit_separators() {
+    assert_eq!(number("1_000_000"), 1_000_000.0);
+    assert_eq!(number("1_0.2_5"), 10.25);
+    assert_eq!(number("1e1_0"), 1e10);
+    assert_eq!(number("0xff_ff"), 65535.0);
+    assert_eq!(number("0b1010_1010"), 170.0);
+}
+
+#[test]
+fn prefixed_integers() {
+    assert_eq!(number("0xff"), 255.0);
+    assert_eq!(number("0xDEADbeef"), 3735928559.0);
+    assert_eq!(number("0b1010"), 10.0);
+    assert_eq!(number("0o17"), 15.0);
+    assert_eq!(number("0x0"), 0.0);
+}
+
+#[test]
+fn minus_is_a_separate_token() {
+    assert_eq!(lex("-1.5"), vec![Minus, NumberLiteral(1.5)]);
+    assert_eq!(lex("0x1e-2"), vec![NumberLiteral(30.0), Minus, NumberLiteral(2.0)]);
+}
+
+#[test]
+fn leading_dot_is_rejected() {
+    assert_eq!(error(".5"), "A number can't start with '.', write '0.5' instead");
+    assert_eq!(error(".5e3"), "A number can't start with '.', write '0.5e3' instead");
+    assert_eq!(error("1.5.3"), "A number can't start with '.', write '0.3' instead");
+}
+
+#[test]
+fn misplaced_separators_are_rejected() {
+    for source in ["1__0", "1_", "1_.5", "1_e5", "1e_5", "0x_1", "0b1_"] {
+        assert!(
+            error(source).contains("'_' must be between digits"),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn missing_digits_are_rejected() {
+    assert_eq!(error("0x"), "Missing digits after '0x'");
+    assert_eq!(error("0b"), "Missing digits after '0b'");
+    assert_eq!(error("0o;"), "Missing digits after '0o'");
+    assert_eq!(error("1e"), "Missing digits in the exponent of '1e'");
+    assert_eq!(error("1e+"), "Missing digits in the exponent of '1e+'");
+}
+
+#[test]
+fn invalid_digits_are_rejected() {
+    assert_eq!(error("0b102"), "Invalid digit '2' in binary literal '0b102'");
+    assert_eq!(error("0o8"), "Invalid digit '8' in octal literal '0o8'");
+    assert_eq!(error("0xfg"), "Invalid digit 'g' in hexadecimal literal '0xfg'");
+    assert_eq!(error("12abc"), "Invalid character 'a' in number literal '12abc'");
+    assert_eq!(error("0X1"), "Invalid character 'X' in number literal '0X1'");
+}
+
+#[test]
+fn invalid_number_covers_the_whole_literal() {
+    let tokens: Vec<Token> = Scanner::new("x = 0b1021;").collect();
+    assert!(matches!(tokens[2].token_type, Invalid(_)));
+    assert_eq!((tokens[2].start.col, tokens[2].end.col), (5, 11));
+    assert_eq!(tokens[3].token_type, Semicolon);
+}
+
+#[test]
+fn operators_and_keywords() {
+    assert_eq!(
+        lex("var x = a <= b != !c;"),
+        vec![
+            Var,
+            Identifier("x".into()),
+            Equal,
+            Identifier("a".into()),
+            LessEqual,
+            Identifier("b".into()),
+            BangEqual,
+            Bang,
+            Identifier("c".into()),
+            Semicolon,
+        ]
+    );
+}
+
+#[test]
+fn compound_assignment_operators() {
+    assert_eq!(
+        lex("+= -= *= /= %= + - * / % ="),
+        vec![
+            PlusEqual, MinusEqual, StarEqual, SlashEqual, ModuloEqual, Plus, Minus, Star, Slash,
+            Modulo, Equal
+        ]
+    );
+    assert_eq!(lex("a-=1"), vec![Identifier("a".into()), MinusEqual, NumberLiteral(1.0)]);
+}
+
+#[test]
+fn comments_are_skipped() {
+    assert_eq!(lex("1 # line comment\n2"), vec![NumberLiteral(1.0), NumberLiteral(2.0)]);
+    assert_eq!(lex("1 #( block (nested) ) 2"), vec![NumberLiteral(1.0), NumberLiteral(2.0)]);
+}
+
+#[test]
+fn doc_comments_are_tokens() {
+    assert_eq!(
+        lex("## Adds one.  \n##\n#(* A (nested) block. *)\n#(*)"),
+        vec![DocComment("Adds one."), DocComment(""), DocComment("A (nested) block.")]
+    );
+    let source = "## doc\nfun f() {}\n#(* more *) var a;";
+    assert_eq!(round_trip(source), source);
+}
+
+#[test]
+fn string_escapes() {
+    assert_eq!(
+        lex(r#""a\tb\n\\ \" \$ \u{48}\u{1F600}""#),
+        vec![StringLiteral("a\tb\n\\ \" $ H\u{1F600}".into())]
+    );
+    assert_eq!(error(r#""\q""#), "Unknown escape sequence '\\q'");
+    assert_eq!(error(r#""\u{D800}""#), "'\\u{D800}' is not a valid unicode character");
+    assert_eq!(error(r#""\u41""#), "Expected '{' after '\\u'");
+}
+
+#[test]
+fn raw_strings() {
+    assert_eq!(lex(r#"r"a\n""#), vec![StringLiteral("a\\n".into())]);
+    assert_eq!(
+        lex(r###"r#"say "hi""#"###),
+        vec![StringLiteral("say \"hi\"".into())]
+    );
+}
+
+#[test]
+fn interpolated_strings() {
+    assert_eq!(
+        lex(r#""a ${x} b""#),
+        vec![
+            StringInterpolation("a ".into()),
+            Identifier("x".into()),
+            StringLiteral(" b".into()),
+        ]
+    );
+}
+
+#[test]
+fn unterminated_input_is_flagged() {
+    for source in ["\"abc", "#( comment", "\"${1"] {
+        let mut scanner = Scanner::new(source);
+        scanner.by_ref().for_each(drop);
+        assert!(scanner.unterminated, "{:?}", source);
+    }
+}
+
+fn round_trip(source: &str) -> String {
+    let mut scanner = Scanner::with_trivia(source);
+    let tokens: Vec<Token> = scanner.by_ref().collect();
+    lossless_source(&tokens, &scanner.end_trivia)
+}
+
+#[test]
+fn trivia_round_trips_the_source() {
+    let sources = [
+        "",
+        "   \n# only a comment",
+        "var a = 1; # trailing comment\n\n#( block\n  #( nested ) )\nprint a;\n",
+        "print \"tab\\t ${ a + 1 } \\u{48}\" + r#\"raw \"x\"\"#;\r\n",
+        "fun f(x) {\n\treturn x * 0x_ff;  \n}",
+        "print \"bad \\q escape\";",
+        "print \"unterminated",
+        include_str!("../code/main.lox"),
+    ];
+    for source in sources {
+        assert_eq!(round_trip(source), source);
+    }
+}
+
+#[test]
+fn trivia_is_split_at_the_end_of_the_line() {
+    let tokens: Vec<Token> = Scanner::with_trivia("a # one\n  # two\nb").collect();
+    let a = tokens[0].trivia.as_deref().unwrap();
+    let b = tokens[1].trivia.as_deref().unwrap();
+    let kinds = |trivia: &[Trivia]| trivia.iter().map(|t| t.kind).collect::<Vec<_>>();
+    assert_eq!(a.text, "a");
+    assert_eq!(
+        kinds(&a.trailing),
+        vec![TriviaKind::Whitespace, TriviaKind::LineComment, TriviaKind::Whitespace]
+    );
+    assert_eq!(
+        kinds(&b.leading),
+        vec![TriviaKind::Whitespace, TriviaKind::LineComment, TriviaKind::Whitespace]
+    );
+    assert_eq!(b.leading[1].text, "# two");
+}
+
+#[test]
+fn trivia_is_off_by_default() {
+    let mut scanner = Scanner::new("a # comment\n");
+    assert!(scanner.next().unwrap().trivia.is_none());
+    assert!(scanner.next().is_none());
+    assert!(scanner.end_trivia.is_empty());
+}
+
+#[test]
+fn spans_are_byte_ranges() {
+    let source = "var é = \"ü ${ñ}\"; # ✓\nprint é;";
+    let tokens: Vec<Token> = Scanner::new(source).collect();
+    let texts: Vec<&str> = tokens
+        .iter()
+        .map(|token| &source[Span::new(token.start, token.end).range()])
+        .collect();
+    assert_eq!(
+        texts,
+        vec!["var", "é", "=", "\"ü ${", "ñ", "}\"", ";", "print", "é", ";"]
+    );
+    // 'é' takes two bytes but one column
+    assert_eq!((tokens[2].start.col, tokens[2].start.index), (7, 7));
+    assert_eq!((tokens[3].start.col, tokens[3].start.index), (9, 9));
+    assert_eq!((tokens[4].start.col, tokens[4].start.index), (14, 15));
+    assert_eq!(tokens[7].start.line, 2);
+}
+
+#[test]
+fn tokens_borrow_the_source() {
+    let tokens = lex(r#"name "plain" "esc\n" r"raw""#);
+    assert!(matches!(tokens[0], Identifier(_)));
+    assert!(matches!(tokens[1], StringLiteral(Cow::Borrowed("plain"))));
+    assert!(matches!(tokens[2], StringLiteral(Cow::Owned(_))));
+    assert!(matches!(tokens[3], StringLiteral(Cow::Borrowed("raw"))));
+}
+
+#[test]
+fn tokens_are_scanned_on_demand() {
+    // the unterminated string at the end isn't reached until the tokens before it are taken
+    let mut scanner = Scanner::new("a b \"open");
+    assert_eq!(scanner.next().map(|token| token.token_type), Some(Identifier("a".into())));
+    assert!(!scanner.unterminated);
+    assert_eq!(scanner.next().map(|token| token.token_type), Some(Identifier("b".into())));
+    assert!(matches!(scanner.next().map(|token| token.token_type), Some(Invalid(_))));
+    assert!(scanner.unterminated);
+    assert!(scanner.next().is_none());
+}
+
+#[test]
+fn identifiers_follow_uax_31() {
+    assert_eq!(
+        lex("größe _x1 π2 日本"),
+        vec![
+            Identifier("größe".into()),
+            Identifier("_x1".into()),
+            Identifier("π2".into()),
+            Identifier("日本".into()),
+        ]
+    );
+    // not XID_Start, and a zero width space can't hide inside a name
+    assert_eq!(error("²x"), "Unexpected character '²'");
+    assert_eq!(error("a\u{200B}b"), "Unexpected character '\u{200B}'");
+}
+
+#[test]
+fn identifiers_are_nfc_normalized() {
+    let composed = lex("caf\u{e9}");
+    let decomposed = lex("cafe\u{301}");
+    assert_eq!(composed, decomposed);
+    assert!(matches!(composed[0], Identifier(Cow::Borrowed(_))));
+    assert!(matches!(decomposed[0], Identifier(Cow::Owned(_))));
+}
+
+#[test]
+fn columns_are_display_columns() {
+    let start_cols =
+        |source| -> Vec<usize> { Scanner::new(source).map(|token| token.start.col).collect() };
+    // wide glyphs take two columns
+    assert_eq!(start_cols("日本 = 1"), vec![1, 6, 8]);
+    // combining marks take none
+    assert_eq!(start_cols("e\u{301}e\u{301} = 1"), vec![1, 4, 6]);
+    // an emoji sequence is as wide as a single emoji
+    assert_eq!(start_cols("\"👨‍👩‍👧\" x"), vec![1, 6]);
+}